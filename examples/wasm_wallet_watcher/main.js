@@ -22,7 +22,7 @@ export function onAddressEvent(address, tx_count, balance) {
   const row = rows[i];
   const cells = row.getElementsByTagName('td');
   if (balance) {
-    const newBalance = (balance.mempool.funded + balance.confirmed.funded) - (balance.mempool.spent + balance.confirmed.spent);
+    const newBalance = balance.confirmed + balance.immature + balance.trusted_pending + balance.untrusted_pending - balance.outgoing_pending;
     const prevBalance = Number(cells[1].textContent.slice(0, -4)) * 100_000_000;
     cells[1].textContent = `${(newBalance / 100_000_000).toFixed(8)} BTC`;
     cells[2].textContent = `${tx_count}`;
@@ -4,7 +4,7 @@ use log::Level;
 use tokio::sync::Mutex;
 use wasm_bindgen::prelude::*;
 use bitcoin::{Address, Network, ScriptBuf};
-use mwck::wallet::{address, Wallet, Options, Event};
+use mwck::wallet::{address, Wallet, Options, Event, RateLimit, DEFAULT_EVENT_JOURNAL_CAPACITY, DEFAULT_MAX_CONCURRENT_SYNCS, DEFAULT_RECONCILE_INTERVAL};
 use wasm_bindgen_futures::future_to_promise;
 
 #[wasm_bindgen(module = "/main.js")]
@@ -34,6 +34,12 @@ impl JsWallet {
             wallet: Arc::new(Mutex::new(Wallet::new(&Options {
                 hostname: host,
                 secure: false,
+                max_concurrent_syncs: DEFAULT_MAX_CONCURRENT_SYNCS,
+                rate_limit: RateLimit::default(),
+                event_journal_capacity: DEFAULT_EVENT_JOURNAL_CAPACITY,
+                saved_journal: None,
+                reconcile_interval: DEFAULT_RECONCILE_INTERVAL,
+                track_prices: false,
             }).unwrap()))
         }
     }
@@ -63,10 +69,22 @@ impl JsWallet {
         wasm_bindgen_futures::spawn_local(async move {
             let mut ready_addresses: HashSet<ScriptBuf> = HashSet::new();
             loop {
-                match event_receiver.recv().await {
-                    Ok(Event::Initializing) => {
+                match event_receiver.recv().await.map(|sequenced| sequenced.event) {
+                    Ok(Event::Initializing | Event::SyncProgress { .. } | Event::Snapshot(_)) => {
                         //
                     }
+                    Ok(Event::AddressSyncFailed { scriptpubkey, error, attempts }) => {
+                        log::warn!("failed to load address {} (attempt {}): {}", scriptpubkey, attempts, error);
+                    }
+                    Ok(Event::TaskFailed { task, error }) => {
+                        log::error!("wallet task {} failed: {}", task, error);
+                    }
+                    Ok(Event::Inconsistency { scriptpubkey, .. }) => {
+                        log::warn!("address out of sync, resyncing {}", scriptpubkey);
+                    }
+                    Ok(Event::OutpointSpent { outpoint, spending_txid, .. }) => {
+                        log::debug!("outpoint {} spent by {}", outpoint, spending_txid);
+                    }
                     Ok(Event::Disconnected) => {
                         log::debug!("wallet disconnected");
                         ready_addresses.clear();
@@ -80,7 +98,7 @@ impl JsWallet {
                             onAddressEvent(address, state.transactions.len(), balance);
                         }
                     }
-                    Ok(Event::AddressEvent(address_event)) => {
+                    Ok(Event::AddressEvent(address_event) | Event::AddressResync(address_event)) => {
                         match &address_event {
                             address::Event::Mempool(scriptpubkey, _) |
                             address::Event::Confirmed(scriptpubkey, _) |
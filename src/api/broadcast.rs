@@ -0,0 +1,377 @@
+//! Transaction submission endpoints
+
+use std::collections::HashMap;
+
+use bitcoin::amount::serde::{as_btc, as_sat};
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::{Amount, Transaction, Txid, Wtxid};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::Client;
+
+/// Whether the backend accepted a submitted transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Submission {
+    Accepted(Txid),
+    /// with the reason given by the backend, e.g. `bad-txns-inputs-missingorspent`
+    Rejected(String),
+}
+
+impl Submission {
+    #[must_use]
+    pub const fn is_accepted(&self) -> bool {
+        matches!(self, Self::Accepted(_))
+    }
+}
+
+/// What the backend made of one transaction of a package, or of a mempool acceptance test
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum TxOutcome {
+    Accepted {
+        vsize: u64,
+        #[serde(with = "as_sat")]
+        fee: Amount,
+        /// in sat/vB, taking into account the other transactions it was assessed with
+        effective_feerate: Option<f64>,
+    },
+    /// with the reason given by the backend
+    Rejected(String),
+}
+
+/// The outcome for one transaction, see [`Client::test_mempool_accept`] and [`Client::broadcast_package`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxResult {
+    pub txid: Txid,
+    pub wtxid: Wtxid,
+    pub outcome: TxOutcome,
+}
+
+impl TxResult {
+    #[must_use]
+    pub const fn is_accepted(&self) -> bool {
+        matches!(self.outcome, TxOutcome::Accepted { .. })
+    }
+
+    fn rejected(tx: &Transaction, reason: &str) -> Self {
+        Self {
+            txid: tx.txid(),
+            wtxid: tx.wtxid(),
+            outcome: TxOutcome::Rejected(reason.to_string()),
+        }
+    }
+}
+
+/// The result of submitting a package with [`Client::broadcast_package`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageSubmission {
+    /// `success`, or why the package as a whole failed
+    pub message: String,
+    /// in the same order as the submitted transactions
+    pub results: Vec<TxResult>,
+    /// mempool transactions evicted by the package
+    pub replaced: Vec<Txid>,
+}
+
+impl PackageSubmission {
+    fn new(txs: &[Transaction], mut raw: RawPackageResult) -> Self {
+        let results = txs
+            .iter()
+            .map(|tx| match raw.tx_results.remove(&tx.wtxid()) {
+                Some(result) => TxResult {
+                    txid: result.txid,
+                    wtxid: tx.wtxid(),
+                    outcome: outcome(result.vsize, result.fees, result.error),
+                },
+                // bitcoind stops assessing a package at its first failure
+                None => TxResult::rejected(tx, &raw.package_msg),
+            })
+            .collect();
+        Self {
+            message: raw.package_msg,
+            results,
+            replaced: raw.replaced_transactions,
+        }
+    }
+
+    #[must_use]
+    pub fn is_accepted(&self) -> bool {
+        self.results.iter().all(TxResult::is_accepted)
+    }
+}
+
+/// bitcoind RPC error codes for a transaction it assessed and refused:
+/// `RPC_DESERIALIZATION_ERROR`, `RPC_VERIFY_ERROR`, `RPC_VERIFY_REJECTED` and `RPC_VERIFY_ALREADY_IN_CHAIN`
+const REJECT_CODES: [i32; 4] = [-22, -25, -26, -27];
+
+/// A bitcoind RPC error, relayed by the backend in the body of a 400
+#[derive(Deserialize)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+/// The reason bitcoind gave for refusing a transaction, if `body` relays one
+///
+/// The backend relays the RPC error either on its own or after a `... RPC error: ` prefix.
+fn reject_reason(body: &str) -> Option<String> {
+    let json = body.find('{').map_or(body, |start| &body[start..]);
+    let error: RpcError = serde_json::from_str(json.trim()).ok()?;
+    REJECT_CODES.contains(&error.code).then_some(error.message)
+}
+
+/// Split a response to a transaction submission into a successful response,
+/// or the reason the transaction was refused if it is a 400 relaying one
+async fn rejection(response: Response) -> Result<Result<Response, String>, esplora_client::Error> {
+    let status = response.status();
+    if status != StatusCode::BAD_REQUEST {
+        return Ok(Ok(response.error_for_status()?));
+    }
+    let body = response.text().await?;
+    reject_reason(&body)
+        .map(Err)
+        .ok_or(esplora_client::Error::HttpResponse(status.as_u16()))
+}
+
+/// Fees as reported by bitcoind, in BTC and BTC/kvB
+#[derive(Deserialize)]
+struct RawFees {
+    #[serde(with = "as_btc")]
+    base: Amount,
+    #[serde(rename = "effective-feerate")]
+    effective_feerate: Option<f64>,
+}
+
+/// An entry of bitcoind's `testmempoolaccept` result
+#[derive(Deserialize)]
+struct RawTestResult {
+    txid: Txid,
+    wtxid: Wtxid,
+    #[serde(default)]
+    allowed: bool,
+    vsize: Option<u64>,
+    fees: Option<RawFees>,
+    #[serde(rename = "reject-reason")]
+    reject_reason: Option<String>,
+    #[serde(rename = "package-error")]
+    package_error: Option<String>,
+}
+
+/// A transaction's entry in bitcoind's `submitpackage` result
+#[derive(Deserialize)]
+struct RawPackageTxResult {
+    txid: Txid,
+    vsize: Option<u64>,
+    fees: Option<RawFees>,
+    error: Option<String>,
+}
+
+/// bitcoind's `submitpackage` result
+#[derive(Deserialize)]
+struct RawPackageResult {
+    package_msg: String,
+    /// keyed by wtxid
+    #[serde(rename = "tx-results", default)]
+    tx_results: HashMap<Wtxid, RawPackageTxResult>,
+    #[serde(rename = "replaced-transactions", default)]
+    replaced_transactions: Vec<Txid>,
+}
+
+fn outcome(vsize: Option<u64>, fees: Option<RawFees>, error: Option<String>) -> TxOutcome {
+    match (error, vsize, fees) {
+        (None, Some(vsize), Some(fees)) => TxOutcome::Accepted {
+            vsize,
+            fee: fees.base,
+            // from BTC/kvB
+            effective_feerate: fees.effective_feerate.map(|feerate| feerate * 100_000.0),
+        },
+        (error, ..) => TxOutcome::Rejected(error.unwrap_or_else(|| "unknown".to_string())),
+    }
+}
+
+impl From<RawTestResult> for TxResult {
+    fn from(raw: RawTestResult) -> Self {
+        let error = if raw.allowed {
+            None
+        } else {
+            Some(raw.reject_reason.or(raw.package_error).unwrap_or_else(|| "not allowed".to_string()))
+        };
+        Self {
+            txid: raw.txid,
+            wtxid: raw.wtxid,
+            outcome: outcome(raw.vsize, raw.fees, error),
+        }
+    }
+}
+
+#[allow(clippy::missing_errors_doc)]
+impl Client {
+    /// POST the hex of `txs` as a JSON array to `path`, relative to the API base URL
+    ///
+    /// Returns the reason bitcoind gave as the `Err` of the inner result
+    /// if it refused to even assess the transactions, see [`Client::broadcast_tx`]
+    async fn post_txs<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        txs: &[Transaction],
+    ) -> Result<Result<T, String>, esplora_client::Error> {
+        let url = format!("{}{path}", self.client.url());
+        let body: Vec<String> = txs.iter().map(serialize_hex).collect();
+        let response = self
+            .scheduler
+            .send(|| self.client.client().post(&url).json(&body))
+            .await?;
+        match rejection(response).await? {
+            Ok(response) => Ok(Ok(response.json::<T>().await?)),
+            Err(reason) => Ok(Err(reason)),
+        }
+    }
+
+    /// Broadcast a transaction, returning the reason if bitcoind rejects it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, or is answered with anything but a rejection
+    pub async fn broadcast_tx(&self, tx: &Transaction) -> Result<Submission, esplora_client::Error> {
+        let url = format!("{}/tx", self.client.url());
+        let body = serialize_hex(tx);
+        let response = self
+            .scheduler
+            .send(|| self.client.client().post(&url).body(body.clone()))
+            .await?;
+        Ok(match rejection(response).await? {
+            Ok(_) => Submission::Accepted(tx.txid()),
+            Err(reason) => Submission::Rejected(reason),
+        })
+    }
+
+    /// Check whether `txs` would be accepted into the mempool, without broadcasting them
+    ///
+    /// `txs` may be a single transaction, or a package of parents and their child,
+    /// parents first. Returns a result for each transaction, in the same order.
+    pub async fn test_mempool_accept(&self, txs: &[Transaction]) -> Result<Vec<TxResult>, esplora_client::Error> {
+        let results = match self.post_txs::<Vec<RawTestResult>>("/v1/txs/test", txs).await? {
+            Ok(results) => results.into_iter().map(TxResult::from).collect(),
+            Err(reason) => txs.iter().map(|tx| TxResult::rejected(tx, &reason)).collect(),
+        };
+        Ok(results)
+    }
+
+    /// Submit a package of transactions to be accepted into the mempool together,
+    /// so that a child can pay for parents which could not be accepted alone
+    ///
+    /// `txs` must be a child and its unconfirmed parents, parents first.
+    pub async fn broadcast_package(&self, txs: &[Transaction]) -> Result<PackageSubmission, esplora_client::Error> {
+        match self.post_txs::<RawPackageResult>("/v1/txs/package", txs).await? {
+            Ok(raw) => Ok(PackageSubmission::new(txs, raw)),
+            Err(reason) => Ok(PackageSubmission {
+                results: txs.iter().map(|tx| TxResult::rejected(tx, &reason)).collect(),
+                message: reason,
+                replaced: Vec::new(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_bitcoind_rejections_are_reject_reasons() {
+        let relayed = r#"sendrawtransaction RPC error: {"code":-26,"message":"min relay fee not met, 100 < 141"}"#;
+        assert_eq!(reject_reason(relayed).as_deref(), Some("min relay fee not met, 100 < 141"));
+        let bare = r#"{"code":-27,"message":"Transaction already in block chain"}"#;
+        assert_eq!(reject_reason(bare).as_deref(), Some("Transaction already in block chain"));
+
+        // errors which say nothing about the transaction itself
+        assert_eq!(reject_reason(r#"sendrawtransaction RPC error: {"code":-28,"message":"Loading block index..."}"#), None);
+        assert_eq!(reject_reason("Bad Request"), None);
+        assert_eq!(reject_reason(""), None);
+    }
+
+    fn tx(value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: Vec::new(),
+            output: vec![bitcoin::TxOut {
+                value,
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        }
+    }
+
+    fn assert_feerate(outcome: &TxOutcome, expected: f64) {
+        let TxOutcome::Accepted { effective_feerate: Some(feerate), .. } = outcome else {
+            panic!("expected an accepted outcome with a feerate, got {outcome:?}");
+        };
+        assert!((feerate - expected).abs() < 1e-9, "{feerate} sat/vB, expected {expected}");
+    }
+
+    #[test]
+    fn parses_mempool_acceptance_tests() {
+        let (allowed, refused) = (tx(1_000), tx(2_000));
+        let json = format!(
+            r#"[
+                {{"txid":"{}","wtxid":"{}","allowed":true,"vsize":141,
+                  "fees":{{"base":0.00000282,"effective-feerate":0.00002,"effective-includes":["{}"]}}}},
+                {{"txid":"{}","wtxid":"{}","allowed":false,"reject-reason":"min relay fee not met"}}
+            ]"#,
+            allowed.txid(),
+            allowed.wtxid(),
+            allowed.wtxid(),
+            refused.txid(),
+            refused.wtxid(),
+        );
+        let results: Vec<TxResult> = serde_json::from_str::<Vec<RawTestResult>>(&json)
+            .unwrap()
+            .into_iter()
+            .map(TxResult::from)
+            .collect();
+
+        assert_eq!(results[0].txid, allowed.txid());
+        assert!(matches!(results[0].outcome, TxOutcome::Accepted { vsize: 141, fee, .. } if fee == Amount::from_sat(282)));
+        // 0.00002 BTC/kvB
+        assert_feerate(&results[0].outcome, 2.0);
+        assert_eq!(results[1].outcome, TxOutcome::Rejected("min relay fee not met".to_string()));
+    }
+
+    #[test]
+    fn parses_package_submissions() {
+        let (parent, child, unassessed) = (tx(1_000), tx(2_000), tx(3_000));
+        let json = format!(
+            r#"{{
+                "package_msg":"transaction failed",
+                "tx-results":{{
+                    "{}":{{"txid":"{}","vsize":110,"fees":{{"base":0.0000011,"effective-feerate":0.000255,"effective-includes":[]}}}},
+                    "{}":{{"txid":"{}","error":"bad-txns-inputs-missingorspent"}}
+                }},
+                "replaced-transactions":["{}"]
+            }}"#,
+            parent.wtxid(),
+            parent.txid(),
+            child.wtxid(),
+            child.txid(),
+            unassessed.txid(),
+        );
+        let raw: RawPackageResult = serde_json::from_str(&json).unwrap();
+        let submission = PackageSubmission::new(&[parent.clone(), child, unassessed.clone()], raw);
+
+        assert!(!submission.is_accepted());
+        assert_eq!(submission.replaced, [unassessed.txid()]);
+        assert_eq!(submission.results[0].wtxid, parent.wtxid());
+        // 0.000255 BTC/kvB
+        assert_feerate(&submission.results[0].outcome, 25.5);
+        assert_eq!(
+            submission.results[1].outcome,
+            TxOutcome::Rejected("bad-txns-inputs-missingorspent".to_string())
+        );
+        // left out of the results, so rejected with the package message
+        assert_eq!(submission.results[2].txid, unassessed.txid());
+        assert_eq!(submission.results[2].outcome, TxOutcome::Rejected("transaction failed".to_string()));
+    }
+}
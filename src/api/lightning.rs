@@ -0,0 +1,299 @@
+//! Lightning network explorer endpoints under `/v1/lightning`
+
+use std::collections::HashMap;
+
+use bitcoin::{OutPoint, Txid};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::Client;
+
+/// A lightning node, from `/v1/lightning/nodes/:public_key`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Node {
+    pub public_key: String,
+    #[serde(default)]
+    pub alias: String,
+    /// in seconds since the epoch
+    #[serde(default)]
+    pub first_seen: Option<u64>,
+    /// in seconds since the epoch
+    #[serde(default)]
+    pub updated_at: Option<u64>,
+    #[serde(default)]
+    pub color: Option<String>,
+    /// comma separated network addresses
+    #[serde(default)]
+    pub sockets: Option<String>,
+    #[serde(default)]
+    pub active_channel_count: u64,
+    #[serde(default)]
+    pub opened_channel_count: u64,
+    #[serde(default)]
+    pub closed_channel_count: u64,
+    /// total capacity of active channels, in sats
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub capacity: u64,
+    #[serde(default)]
+    pub as_organization: Option<String>,
+    #[serde(default)]
+    pub iso_code: Option<String>,
+}
+
+/// Whether a channel is usable, from the explorer's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "u8", into = "u8")]
+pub enum ChannelStatus {
+    Inactive,
+    Active,
+    Closed,
+}
+
+impl From<u8> for ChannelStatus {
+    fn from(status: u8) -> Self {
+        match status {
+            1 => Self::Active,
+            2 => Self::Closed,
+            _ => Self::Inactive,
+        }
+    }
+}
+
+impl From<ChannelStatus> for u8 {
+    fn from(status: ChannelStatus) -> Self {
+        match status {
+            ChannelStatus::Inactive => 0,
+            ChannelStatus::Active => 1,
+            ChannelStatus::Closed => 2,
+        }
+    }
+}
+
+/// How a channel was closed on chain
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Option<u8>", into = "Option<u8>")]
+pub enum CloseKind {
+    /// not closed, or not yet classified by the explorer
+    #[default]
+    Unknown,
+    /// both sides agreed to close
+    Mutual,
+    /// one side closed unilaterally
+    Force,
+    /// one side broadcast a revoked state, and the other claimed a penalty
+    ForcePenalty,
+}
+
+impl From<Option<u8>> for CloseKind {
+    fn from(reason: Option<u8>) -> Self {
+        match reason {
+            Some(1) => Self::Mutual,
+            Some(2) => Self::Force,
+            Some(3) => Self::ForcePenalty,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl From<CloseKind> for Option<u8> {
+    fn from(kind: CloseKind) -> Self {
+        match kind {
+            CloseKind::Unknown => None,
+            CloseKind::Mutual => Some(1),
+            CloseKind::Force => Some(2),
+            CloseKind::ForcePenalty => Some(3),
+        }
+    }
+}
+
+/// One end of a channel, and the routing policy it advertises
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelNode {
+    pub public_key: String,
+    #[serde(default)]
+    pub alias: String,
+    #[serde(default)]
+    pub base_fee_mtokens: Option<u64>,
+    /// proportional fee, in millionths
+    #[serde(default)]
+    pub fee_rate: Option<u64>,
+    #[serde(default)]
+    pub cltv_delta: Option<u32>,
+    #[serde(default, deserialize_with = "flag")]
+    pub is_disabled: Option<bool>,
+}
+
+/// A lightning channel, from `/v1/lightning/channels/:short_id`
+/// or `/v1/lightning/channels/txids`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Channel {
+    pub id: String,
+    /// `block x index x output`
+    #[serde(default)]
+    pub short_id: Option<String>,
+    /// in sats
+    #[serde(default)]
+    pub capacity: u64,
+    pub transaction_id: Txid,
+    pub transaction_vout: u32,
+    #[serde(default)]
+    pub closing_transaction_id: Option<Txid>,
+    #[serde(default)]
+    pub closing_reason: CloseKind,
+    pub status: ChannelStatus,
+    #[serde(default)]
+    pub node_left: Option<ChannelNode>,
+    #[serde(default)]
+    pub node_right: Option<ChannelNode>,
+}
+
+impl Channel {
+    #[must_use]
+    pub const fn funding_outpoint(&self) -> OutPoint {
+        OutPoint {
+            txid: self.transaction_id,
+            vout: self.transaction_vout,
+        }
+    }
+}
+
+/// Channels opened or closed by a transaction, keyed by output or input index
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxChannels {
+    /// channels closed by spending the input at each index
+    #[serde(default)]
+    pub inputs: HashMap<u32, Channel>,
+    /// channels funded by the output at each index
+    #[serde(default)]
+    pub outputs: HashMap<u32, Channel>,
+}
+
+/// A snapshot of the whole lightning network
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Statistics {
+    /// when the snapshot was taken, as an ISO 8601 date
+    #[serde(default)]
+    pub added: Option<String>,
+    pub channel_count: u64,
+    pub node_count: u64,
+    /// in sats
+    #[serde(deserialize_with = "number_or_string")]
+    pub total_capacity: u64,
+    #[serde(default)]
+    pub tor_nodes: u64,
+    #[serde(default)]
+    pub clearnet_nodes: u64,
+    #[serde(default)]
+    pub unannounced_nodes: u64,
+    #[serde(default)]
+    pub clearnet_tor_nodes: u64,
+    /// in sats
+    #[serde(default)]
+    pub avg_capacity: u64,
+    /// in millionths
+    #[serde(default)]
+    pub avg_fee_rate: u64,
+    #[serde(default)]
+    pub avg_base_fee_mtokens: u64,
+    /// in sats
+    #[serde(default)]
+    pub med_capacity: u64,
+    /// in millionths
+    #[serde(default)]
+    pub med_fee_rate: u64,
+    #[serde(default)]
+    pub med_base_fee_mtokens: u64,
+}
+
+/// Network statistics from `/v1/lightning/statistics/latest`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkStatistics {
+    pub latest: Statistics,
+    /// a week earlier, for comparison
+    #[serde(default)]
+    pub previous: Option<Statistics>,
+}
+
+/// How and when a channel was closed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelClose {
+    pub funding: OutPoint,
+    /// the explorer's id for the channel, if it knows of it
+    pub channel_id: Option<String>,
+    pub closing_txid: Txid,
+    pub kind: CloseKind,
+}
+
+/// Some amounts are sent as strings, to survive javascript
+fn number_or_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(u64),
+        String(String),
+    }
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number),
+        NumberOrString::String(string) => string.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Flags are sent as either booleans or 0/1
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<bool>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Number(u8),
+    }
+    Ok(Option::<Flag>::deserialize(deserializer)?.map(|flag| match flag {
+        Flag::Bool(flag) => flag,
+        Flag::Number(number) => number != 0,
+    }))
+}
+
+#[allow(clippy::missing_errors_doc)]
+impl Client {
+    /// Get a lightning node by its public key
+    pub async fn get_lightning_node(&self, public_key: &str) -> Result<Node, esplora_client::Error> {
+        self.get_json(&format!("/v1/lightning/nodes/{public_key}")).await
+    }
+
+    /// Get a lightning channel by its id or short id
+    pub async fn get_lightning_channel(&self, id: &str) -> Result<Channel, esplora_client::Error> {
+        self.get_json(&format!("/v1/lightning/channels/{id}")).await
+    }
+
+    /// Get the channels opened and closed by each of `txids`, in the same order
+    pub async fn get_lightning_channels_by_txids(&self, txids: &[Txid]) -> Result<Vec<TxChannels>, esplora_client::Error> {
+        let query: Vec<String> = txids.iter().map(|txid| format!("txId[]={txid}")).collect();
+        self.get_json(&format!("/v1/lightning/channels/txids?{}", query.join("&"))).await
+    }
+
+    /// Get the latest statistics for the whole lightning network
+    pub async fn get_lightning_statistics(&self) -> Result<NetworkStatistics, esplora_client::Error> {
+        self.get_json("/v1/lightning/statistics/latest").await
+    }
+
+    /// How the channel funded by `funding` was closed, if it has been closed on chain
+    ///
+    /// The funding output's spend is checked first, so this works for channels the explorer
+    /// doesn't know about, in which case the kind of close is [`CloseKind::Unknown`].
+    pub async fn get_channel_close(&self, funding: &OutPoint) -> Result<Option<ChannelClose>, esplora_client::Error> {
+        let status = self.get_output_status(&funding.txid, u64::from(funding.vout)).await?;
+        let Some(closing_txid) = status.filter(|status| status.spent).and_then(|status| status.txid) else {
+            return Ok(None);
+        };
+        let channel = self
+            .get_lightning_channels_by_txids(&[funding.txid])
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|mut channels| channels.outputs.remove(&funding.vout));
+        Ok(Some(ChannelClose {
+            funding: *funding,
+            channel_id: channel.as_ref().map(|channel| channel.id.clone()),
+            closing_txid,
+            kind: channel.map_or(CloseKind::Unknown, |channel| channel.closing_reason),
+        }))
+    }
+}
@@ -0,0 +1,194 @@
+//! Mempool-specific endpoints, beyond the esplora-compatible subset
+
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+
+use super::Client;
+
+/// Feerates in sat/vB from `/v1/fees/recommended`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendedFees {
+    /// to be included in the next block
+    pub fastest_fee: f64,
+    /// to confirm within about half an hour
+    pub half_hour_fee: f64,
+    /// to confirm within about an hour
+    pub hour_fee: f64,
+    /// to confirm eventually, without overpaying
+    pub economy_fee: f64,
+    /// the lowest feerate the backend will relay
+    pub minimum_fee: f64,
+}
+
+/// A block the backend expects to be mined from its current mempool,
+/// from `/v1/fees/mempool-blocks`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MempoolBlock {
+    /// in bytes
+    pub block_size: u64,
+    /// in virtual bytes
+    pub block_v_size: f64,
+    pub n_tx: u64,
+    /// in sats
+    pub total_fees: u64,
+    /// in sat/vB
+    pub median_fee: f64,
+    /// feerates in sat/vB, from lowest to highest
+    pub fee_range: Vec<f64>,
+}
+
+/// Backlog statistics from `/v1/mempool`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MempoolInfo {
+    pub count: u64,
+    /// total size in virtual bytes
+    pub vsize: u64,
+    /// in sats
+    pub total_fee: u64,
+    /// `(feerate in sat/vB, vsize)` buckets, from highest feerate to lowest
+    pub fee_histogram: Vec<(f64, u64)>,
+}
+
+/// Progress of the current difficulty epoch from `/v1/difficulty-adjustment`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DifficultyAdjustment {
+    /// how far through the epoch, from 0 to 100
+    pub progress_percent: f64,
+    /// estimated change at the next retarget, as a percentage
+    pub difficulty_change: f64,
+    /// in milliseconds since the epoch
+    pub estimated_retarget_date: u64,
+    pub remaining_blocks: u32,
+    /// in milliseconds
+    pub remaining_time: u64,
+    /// change at the previous retarget, as a percentage
+    pub previous_retarget: f64,
+    /// time of the previous retarget, in seconds since the epoch
+    #[serde(default)]
+    pub previous_time: Option<u64>,
+    pub next_retarget_height: u32,
+    /// average block interval this epoch, in milliseconds
+    pub time_avg: u64,
+    /// average block interval this epoch, adjusted for the time offset, in milliseconds
+    #[serde(default)]
+    pub adjusted_time_avg: Option<u64>,
+    /// how far ahead of (negative) or behind (positive) schedule the epoch is, in milliseconds
+    pub time_offset: i64,
+    /// blocks expected so far this epoch at one block every ten minutes
+    #[serde(default)]
+    pub expected_blocks: Option<f64>,
+}
+
+/// A transaction in a CPFP package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpfpTx {
+    pub txid: Txid,
+    /// in sats
+    pub fee: u64,
+    /// in weight units
+    pub weight: u64,
+}
+
+/// The unconfirmed ancestors and descendants of a transaction, from `/v1/cpfp/:txid`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CpfpInfo {
+    #[serde(default)]
+    pub ancestors: Vec<CpfpTx>,
+    #[serde(default)]
+    pub descendants: Vec<CpfpTx>,
+    /// the descendant which raises the feerate of the package the most, if any
+    #[serde(default)]
+    pub best_descendant: Option<CpfpTx>,
+    /// feerate in sat/vB the transaction is mined at, taking its package into account
+    #[serde(default)]
+    pub effective_fee_per_vsize: Option<f64>,
+    /// vsize in virtual bytes, adjusted for sigops
+    #[serde(default)]
+    pub adjusted_vsize: Option<f64>,
+}
+
+/// A transaction in an RBF replacement tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RbfTx {
+    pub txid: Txid,
+    /// in sats
+    pub fee: u64,
+    /// in virtual bytes
+    pub vsize: f64,
+    /// total output value, in sats
+    pub value: u64,
+    /// feerate in sat/vB
+    pub rate: f64,
+    /// when the backend first saw it, in seconds since the epoch
+    pub time: u64,
+    /// whether it signals replaceability
+    pub rbf: bool,
+    #[serde(default)]
+    pub full_rbf: Option<bool>,
+    #[serde(default)]
+    pub mined: Option<bool>,
+}
+
+/// A transaction along with the transactions it replaced, recursively
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RbfTree {
+    pub tx: RbfTx,
+    /// when the replacement was seen, in seconds since the epoch
+    pub time: u64,
+    /// whether any replacement in the tree didn't signal replaceability
+    #[serde(default)]
+    pub full_rbf: bool,
+    #[serde(default)]
+    pub replaces: Vec<Self>,
+}
+
+/// The replacement history of a transaction, from `/v1/tx/:txid/rbf`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RbfHistory {
+    /// the latest replacement of the transaction and everything it replaced, if any
+    #[serde(default)]
+    pub replacements: Option<RbfTree>,
+    /// transactions directly replaced by this one
+    #[serde(default)]
+    pub replaces: Vec<Txid>,
+}
+
+#[allow(clippy::missing_errors_doc)]
+impl Client {
+    /// Get the recommended feerates for a range of confirmation targets
+    pub async fn get_recommended_fees(&self) -> Result<RecommendedFees, esplora_client::Error> {
+        self.get_json("/v1/fees/recommended").await
+    }
+
+    /// Get the blocks the backend expects to be mined next, from its current mempool
+    pub async fn get_mempool_blocks(&self) -> Result<Vec<MempoolBlock>, esplora_client::Error> {
+        self.get_json("/v1/fees/mempool-blocks").await
+    }
+
+    /// Get the size and fee distribution of the mempool
+    pub async fn get_mempool_info(&self) -> Result<MempoolInfo, esplora_client::Error> {
+        self.get_json("/v1/mempool").await
+    }
+
+    /// Get the progress of the current difficulty epoch and the estimated next adjustment
+    pub async fn get_difficulty_adjustment(&self) -> Result<DifficultyAdjustment, esplora_client::Error> {
+        self.get_json("/v1/difficulty-adjustment").await
+    }
+
+    /// Get the unconfirmed ancestors and descendants of a transaction,
+    /// and the feerate it is effectively mined at as a result
+    pub async fn get_cpfp_info(&self, txid: &Txid) -> Result<CpfpInfo, esplora_client::Error> {
+        self.get_json(&format!("/v1/cpfp/{txid}")).await
+    }
+
+    /// Get the transactions a transaction replaced, and any transactions which replaced it
+    pub async fn get_rbf_history(&self, txid: &Txid) -> Result<RbfHistory, esplora_client::Error> {
+        self.get_json(&format!("/v1/tx/{txid}/rbf")).await
+    }
+}
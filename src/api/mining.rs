@@ -0,0 +1,276 @@
+//! Mining pool and block audit endpoints
+
+use std::fmt;
+
+use bitcoin::{BlockHash, Txid};
+use serde::{Deserialize, Serialize};
+
+use super::Client;
+
+/// Time period to aggregate mining statistics over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TimePeriod {
+    #[serde(rename = "24h")]
+    Day,
+    #[serde(rename = "3d")]
+    ThreeDays,
+    #[serde(rename = "1w")]
+    Week,
+    #[serde(rename = "1m")]
+    Month,
+    #[serde(rename = "3m")]
+    ThreeMonths,
+    #[serde(rename = "6m")]
+    SixMonths,
+    #[serde(rename = "1y")]
+    Year,
+    #[serde(rename = "2y")]
+    TwoYears,
+    #[serde(rename = "3y")]
+    ThreeYears,
+    #[serde(rename = "all")]
+    All,
+}
+
+impl fmt::Display for TimePeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Day => "24h",
+            Self::ThreeDays => "3d",
+            Self::Week => "1w",
+            Self::Month => "1m",
+            Self::ThreeMonths => "3m",
+            Self::SixMonths => "6m",
+            Self::Year => "1y",
+            Self::TwoYears => "2y",
+            Self::ThreeYears => "3y",
+            Self::All => "all",
+        })
+    }
+}
+
+/// A pool's share of the blocks mined in a period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolStats {
+    pub pool_id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub link: Option<String>,
+    /// identifies the pool in other mining endpoints
+    pub slug: String,
+    pub block_count: u64,
+    pub rank: u64,
+    #[serde(default)]
+    pub empty_blocks: u64,
+    /// how closely its blocks matched the expected templates, as a percentage
+    #[serde(default)]
+    pub avg_match_rate: Option<f64>,
+}
+
+/// Blocks mined by each pool in a period, from `/v1/mining/pools/:period`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pools {
+    /// most blocks first
+    pub pools: Vec<PoolStats>,
+    pub block_count: u64,
+    /// in hashes per second
+    pub last_estimated_hashrate: f64,
+}
+
+/// A pool's hashrate at a point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolHashrate {
+    /// in seconds since the epoch
+    pub timestamp: u64,
+    /// in hashes per second
+    pub avg_hashrate: f64,
+    /// share of the network hashrate, from 0 to 1
+    pub share: f64,
+    pub pool_name: String,
+}
+
+/// Average block reward at a point in time, from `/v1/mining/blocks/rewards/:period`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockRewards {
+    pub avg_height: f64,
+    /// in seconds since the epoch
+    pub timestamp: u64,
+    /// subsidy plus fees, in sats
+    pub avg_rewards: u64,
+}
+
+/// Average total fees per block at a point in time, from `/v1/mining/blocks/fees/:period`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockFees {
+    pub avg_height: f64,
+    /// in seconds since the epoch
+    pub timestamp: u64,
+    /// in sats
+    pub avg_fees: u64,
+}
+
+/// Percentiles of block feerates at a point in time, from `/v1/mining/blocks/fee-rates/:period`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BlockFeeRates {
+    #[serde(rename = "avgHeight")]
+    pub avg_height: f64,
+    /// in seconds since the epoch
+    pub timestamp: u64,
+    /// the minimum, 10th, 25th, 50th, 75th, 90th percentile and maximum feerates in sat/vB
+    #[serde(rename = "avgFee_0")]
+    pub min: f64,
+    #[serde(rename = "avgFee_10")]
+    pub p10: f64,
+    #[serde(rename = "avgFee_25")]
+    pub p25: f64,
+    #[serde(rename = "avgFee_50")]
+    pub median: f64,
+    #[serde(rename = "avgFee_75")]
+    pub p75: f64,
+    #[serde(rename = "avgFee_90")]
+    pub p90: f64,
+    #[serde(rename = "avgFee_100")]
+    pub max: f64,
+}
+
+/// The pool credited with mining a block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockPool {
+    pub id: u64,
+    pub name: String,
+    pub slug: String,
+}
+
+/// Mining details of a block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockExtras {
+    #[serde(default)]
+    pub pool: Option<BlockPool>,
+    /// subsidy plus fees, in sats
+    #[serde(default)]
+    pub reward: Option<u64>,
+    /// in sats
+    #[serde(default)]
+    pub total_fees: Option<u64>,
+    /// in sat/vB
+    #[serde(default)]
+    pub median_fee: Option<f64>,
+    /// how closely the block matched the expected template, as a percentage
+    #[serde(default)]
+    pub match_rate: Option<f64>,
+}
+
+/// A block along with its mining details, from `/v1/block/:hash`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockExtended {
+    pub id: BlockHash,
+    pub height: u32,
+    /// in seconds since the epoch
+    pub timestamp: u64,
+    pub tx_count: u64,
+    pub extras: BlockExtras,
+}
+
+/// How a block compared to the template the backend expected it to be,
+/// from `/v1/block/:hash/audit-summary`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditSummary {
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub id: Option<BlockHash>,
+    /// expected in the block, but not included
+    #[serde(default)]
+    pub missing_txs: Vec<Txid>,
+    /// included in the block, but not expected
+    #[serde(default)]
+    pub added_txs: Vec<Txid>,
+    /// too recent to have been expected
+    #[serde(default)]
+    pub fresh_txs: Vec<Txid>,
+    #[serde(default)]
+    pub sigop_txs: Vec<Txid>,
+    #[serde(default)]
+    pub fullrbf_txs: Vec<Txid>,
+    #[serde(default)]
+    pub accelerated_txs: Vec<Txid>,
+    /// how closely the block matched the template, as a percentage
+    #[serde(default)]
+    pub match_rate: Option<f64>,
+    /// in sats
+    #[serde(default)]
+    pub expected_fees: Option<u64>,
+    #[serde(default)]
+    pub expected_weight: Option<u64>,
+}
+
+impl AuditSummary {
+    /// Whether `txid` was expected in the block but left out
+    #[must_use]
+    pub fn is_missing(&self, txid: &Txid) -> bool {
+        self.missing_txs.contains(txid)
+    }
+
+    /// Whether `txid` was included in the block without being expected
+    #[must_use]
+    pub fn is_added(&self, txid: &Txid) -> bool {
+        self.added_txs.contains(txid)
+    }
+}
+
+#[allow(clippy::missing_errors_doc)]
+impl Client {
+    /// Get the blocks mined by each pool over `period`
+    pub async fn get_mining_pools(&self, period: TimePeriod) -> Result<Pools, esplora_client::Error> {
+        self.get_json(&format!("/v1/mining/pools/{period}")).await
+    }
+
+    /// Get the hashrate history of the pool identified by `slug`
+    pub async fn get_pool_hashrate(&self, slug: &str) -> Result<Vec<PoolHashrate>, esplora_client::Error> {
+        self.get_json(&format!("/v1/mining/pool/{slug}/hashrate")).await
+    }
+
+    /// Get the hashrate history of every pool over `period`
+    pub async fn get_pools_hashrate(&self, period: TimePeriod) -> Result<Vec<PoolHashrate>, esplora_client::Error> {
+        self.get_json(&format!("/v1/mining/hashrate/pools/{period}")).await
+    }
+
+    /// Get the average block reward over `period`
+    pub async fn get_block_rewards(&self, period: TimePeriod) -> Result<Vec<BlockRewards>, esplora_client::Error> {
+        self.get_json(&format!("/v1/mining/blocks/rewards/{period}")).await
+    }
+
+    /// Get the average total fees per block over `period`
+    pub async fn get_block_fees(&self, period: TimePeriod) -> Result<Vec<BlockFees>, esplora_client::Error> {
+        self.get_json(&format!("/v1/mining/blocks/fees/{period}")).await
+    }
+
+    /// Get percentiles of the feerates paid in blocks over `period`
+    pub async fn get_block_fee_rates(&self, period: TimePeriod) -> Result<Vec<BlockFeeRates>, esplora_client::Error> {
+        self.get_json(&format!("/v1/mining/blocks/fee-rates/{period}")).await
+    }
+
+    /// Get a block along with the pool which mined it and its rewards
+    pub async fn get_block_extended(&self, block_hash: &BlockHash) -> Result<BlockExtended, esplora_client::Error> {
+        self.get_json(&format!("/v1/block/{block_hash}")).await
+    }
+
+    /// Get how a block compared to the template the backend expected,
+    /// or `None` if the backend has not audited it
+    pub async fn get_block_audit_summary(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<AuditSummary>, esplora_client::Error> {
+        match self.get_opt(&format!("/v1/block/{block_hash}/audit-summary")).await? {
+            Some(response) => Ok(Some(response.json().await?)),
+            None => Ok(None),
+        }
+    }
+}
@@ -1,23 +1,337 @@
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoin::hashes::hex::FromHex;
 use bitcoin::{
     hashes::{sha256, Hash},
-    ScriptBuf, Txid,
+    Block, BlockHash, MerkleBlock, ScriptBuf, Transaction, Txid,
 };
-use esplora_client::{AsyncClient as EsploraClient, Builder, Error, Tx};
+use reqwest::{Response, StatusCode};
+use esplora_client::{
+    AsyncClient as EsploraClient, BlockStatus, BlockSummary, Builder, MerkleProof, OutputStatus,
+    Tx, TxStatus,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cancel::CancellationToken;
+
+pub mod broadcast;
+pub mod lightning;
+pub mod mempool;
+pub mod mining;
+pub mod prices;
+mod scheduler;
+use scheduler::Scheduler;
+pub use scheduler::RateLimit;
+
+/// Seconds before an unanswered REST request is abandoned (and retried)
+const REQUEST_TIMEOUT: u64 = 30;
+
+#[derive(Debug)]
+pub enum Error {
+    EsploraError(esplora_client::Error),
+    Cancelled,
+    /// the [`RateLimit`] given to [`Client::new`] can't be enforced
+    InvalidRateLimit(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<esplora_client::Error> for Error {
+    fn from(err: esplora_client::Error) -> Self {
+        Self::EsploraError(err)
+    }
+}
+
+/// Where to stop paging back through an address history
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HistoryLimit {
+    /// stop once this transaction has been fetched
+    pub until_txid: Option<Txid>,
+    /// stop once a transaction confirmed below this height has been fetched
+    pub until_height: Option<u32>,
+    /// stop once at least this many transactions have been fetched
+    pub max_txs: Option<usize>,
+}
+
+/// Funding and spending totals for an address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxoStats {
+    pub tx_count: u64,
+    pub funded_txo_count: u64,
+    pub funded_txo_sum: u64,
+    pub spent_txo_count: u64,
+    pub spent_txo_sum: u64,
+}
+
+impl TxoStats {
+    pub(crate) const fn add(&mut self, other: &Self) {
+        self.tx_count += other.tx_count;
+        self.funded_txo_count += other.funded_txo_count;
+        self.funded_txo_sum += other.funded_txo_sum;
+        self.spent_txo_count += other.spent_txo_count;
+        self.spent_txo_sum += other.spent_txo_sum;
+    }
+
+    pub(crate) const fn remove(&mut self, other: &Self) {
+        self.tx_count = self.tx_count.saturating_sub(other.tx_count);
+        self.funded_txo_count = self.funded_txo_count.saturating_sub(other.funded_txo_count);
+        self.funded_txo_sum = self.funded_txo_sum.saturating_sub(other.funded_txo_sum);
+        self.spent_txo_count = self.spent_txo_count.saturating_sub(other.spent_txo_count);
+        self.spent_txo_sum = self.spent_txo_sum.saturating_sub(other.spent_txo_sum);
+    }
+}
+
+/// Summary of an address from the `/scripthash/:hash` endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressStats {
+    pub chain_stats: TxoStats,
+    pub mempool_stats: TxoStats,
+}
+
+/// Progress of a paginated address history fetch
+#[derive(Debug, Clone, Default)]
+pub struct HistoryCursor {
+    /// txid of the oldest transaction fetched so far,
+    /// used as the `after_txid` of the next page request
+    pub after_txid: Option<Txid>,
+    /// transactions fetched so far, newest first
+    pub fetched: Vec<Tx>,
+    /// number of pages requested so far
+    pub pages_fetched: usize,
+}
+
+impl HistoryCursor {
+    /// Where an interrupted fetch resumes: the newest confirmed transaction fetched so far,
+    /// and the limit for refetching the head of the history back down to it.
+    ///
+    /// If the resume point has been reorged out, that refetch is the whole fetch,
+    /// so it keeps to `limit.max_txs`.
+    fn resume_point(&self, limit: HistoryLimit) -> Option<(Txid, HistoryLimit)> {
+        self.after_txid?;
+        let resume_txid = self.fetched.iter().find(|tx| tx.status.confirmed)?.txid;
+        let head_limit = HistoryLimit {
+            until_txid: Some(resume_txid),
+            max_txs: limit.max_txs,
+            ..HistoryLimit::default()
+        };
+        Some((resume_txid, head_limit))
+    }
+
+    /// Block height of the oldest confirmed transaction fetched so far
+    #[must_use]
+    pub fn oldest_height_reached(&self) -> Option<u32> {
+        self.fetched
+            .iter()
+            .rev()
+            .find(|tx| tx.status.confirmed)
+            .and_then(|tx| tx.status.block_height)
+    }
+
+    /// Replace stale unconfirmed transactions with a freshly fetched `head`
+    /// which runs up to and including `resume_txid`.
+    ///
+    /// Returns false if the resume point has disappeared (e.g. reorged out),
+    /// in which case the head already contains the whole history.
+    fn rebase(&mut self, mut head: Self, resume_txid: Txid) -> bool {
+        if let Some(position) = head.fetched.iter().position(|tx| tx.txid == resume_txid) {
+            head.fetched.truncate(position);
+            self.fetched.retain(|tx| tx.status.confirmed);
+            head.fetched.append(&mut self.fetched);
+            self.fetched = head.fetched;
+            self.pages_fetched += head.pages_fetched;
+            true
+        } else {
+            *self = head;
+            false
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Client {
     pub client: EsploraClient,
+    scheduler: Scheduler,
 }
 
 impl Client {
-    pub fn new(url: &str) -> Result<Self, esplora_client::Error> {
-        let builder = Builder::new(url);
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client can't be built, or `rate_limit` is invalid
+    pub fn new(url: &str, rate_limit: RateLimit) -> Result<Self, Error> {
+        let builder = Builder::new(url).timeout(REQUEST_TIMEOUT);
         let async_client: EsploraClient = builder.build_async()?;
         Ok(Self {
             client: async_client,
+            scheduler: Scheduler::new(rate_limit)?,
         })
     }
 
+    /// Send a GET request for `path`, relative to the API base URL, through the [`Scheduler`]
+    async fn get(&self, path: &str) -> Result<Response, esplora_client::Error> {
+        let url = format!("{}{path}", self.client.url());
+        Ok(self
+            .scheduler
+            .send(|| self.client.client().get(&url))
+            .await?
+            .error_for_status()?)
+    }
+
+    /// Like [`Client::get`], but a 404 is `None` rather than an error
+    async fn get_opt(&self, path: &str) -> Result<Option<Response>, esplora_client::Error> {
+        let url = format!("{}{path}", self.client.url());
+        let response = self.scheduler.send(|| self.client.client().get(&url)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?))
+    }
+
+    /// Send a GET request for `path`, relative to the API base URL,
+    /// through the [`Scheduler`] and parse the JSON response
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, esplora_client::Error> {
+        Ok(self.get(path).await?.json::<T>().await?)
+    }
+}
+
+/// The `esplora_client::AsyncClient` requests, run through the [`Scheduler`]
+#[allow(clippy::missing_errors_doc)]
+impl Client {
+    /// Get a [`Transaction`] option given its [`Txid`]
+    pub async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, esplora_client::Error> {
+        match self.get_opt(&format!("/tx/{txid}/raw")).await? {
+            Some(response) => Ok(Some(deserialize(&response.bytes().await?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a [`Transaction`] given its [`Txid`]
+    pub async fn get_tx_no_opt(&self, txid: &Txid) -> Result<Transaction, esplora_client::Error> {
+        self.get_tx(txid)
+            .await?
+            .ok_or(esplora_client::Error::TransactionNotFound(*txid))
+    }
+
+    /// Get the [`Txid`] of a transaction given its index in a block with a given hash
+    pub async fn get_txid_at_block_index(
+        &self,
+        block_hash: &BlockHash,
+        index: usize,
+    ) -> Result<Option<Txid>, esplora_client::Error> {
+        match self.get_opt(&format!("/block/{block_hash}/txid/{index}")).await? {
+            Some(response) => Ok(Some(Txid::from_str(response.text().await?.trim())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the status of a [`Transaction`] given its [`Txid`]
+    pub async fn get_tx_status(&self, txid: &Txid) -> Result<TxStatus, esplora_client::Error> {
+        self.get_json(&format!("/tx/{txid}/status")).await
+    }
+
+    /// Get the [`BlockStatus`] given a particular [`BlockHash`]
+    pub async fn get_block_status(&self, block_hash: &BlockHash) -> Result<BlockStatus, esplora_client::Error> {
+        self.get_json(&format!("/block/{block_hash}/status")).await
+    }
+
+    /// Get a [`Block`] given a particular [`BlockHash`]
+    pub async fn get_block_by_hash(&self, block_hash: &BlockHash) -> Result<Option<Block>, esplora_client::Error> {
+        match self.get_opt(&format!("/block/{block_hash}/raw")).await? {
+            Some(response) => Ok(Some(deserialize(&response.bytes().await?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a merkle inclusion proof for a [`Transaction`] with the given [`Txid`]
+    pub async fn get_merkle_proof(&self, tx_hash: &Txid) -> Result<Option<MerkleProof>, esplora_client::Error> {
+        match self.get_opt(&format!("/tx/{tx_hash}/merkle-proof")).await? {
+            Some(response) => Ok(Some(response.json().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a [`MerkleBlock`] inclusion proof for a [`Transaction`] with the given [`Txid`]
+    pub async fn get_merkle_block(&self, tx_hash: &Txid) -> Result<Option<MerkleBlock>, esplora_client::Error> {
+        match self.get_opt(&format!("/tx/{tx_hash}/merkleblock-proof")).await? {
+            Some(response) => Ok(Some(deserialize(&Vec::from_hex(response.text().await?.trim())?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the spending status of an output given a [`Txid`] and the output index
+    pub async fn get_output_status(&self, txid: &Txid, index: u64) -> Result<Option<OutputStatus>, esplora_client::Error> {
+        match self.get_opt(&format!("/tx/{txid}/outspend/{index}")).await? {
+            Some(response) => Ok(Some(response.json().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Broadcast a [`Transaction`], see [`Client::broadcast_tx`] to find out why one was rejected
+    pub async fn broadcast(&self, transaction: &Transaction) -> Result<(), esplora_client::Error> {
+        let url = format!("{}/tx", self.client.url());
+        let body = serialize_hex(transaction);
+        self.scheduler
+            .send(|| self.client.client().post(&url).body(body.clone()))
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Get the current height of the blockchain tip
+    pub async fn get_height(&self) -> Result<u32, esplora_client::Error> {
+        Ok(self.get("/blocks/tip/height").await?.text().await?.trim().parse()?)
+    }
+
+    /// Get the [`BlockHash`] of the current blockchain tip
+    pub async fn get_tip_hash(&self) -> Result<BlockHash, esplora_client::Error> {
+        Ok(BlockHash::from_str(self.get("/blocks/tip/hash").await?.text().await?.trim())?)
+    }
+
+    /// Get the [`BlockHash`] of a specific block height
+    pub async fn get_block_hash(&self, block_height: u32) -> Result<BlockHash, esplora_client::Error> {
+        match self.get_opt(&format!("/block-height/{block_height}")).await? {
+            Some(response) => Ok(BlockHash::from_str(response.text().await?.trim())?),
+            None => Err(esplora_client::Error::HeaderHeightNotFound(block_height)),
+        }
+    }
+
+    /// Get a map from confirmation target (in blocks) to estimated feerate (in sat/vB)
+    pub async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, esplora_client::Error> {
+        self.get_json("/fee-estimates").await
+    }
+
+    /// Get recent block summaries, starting at the tip or at `height` if provided
+    pub async fn get_blocks(&self, height: Option<u32>) -> Result<Vec<BlockSummary>, esplora_client::Error> {
+        match height {
+            Some(height) => self.get_json(&format!("/blocks/{height}")).await,
+            None => self.get_json("/blocks").await,
+        }
+    }
+
+    /// `esplora_client::AsyncClient::scripthash_txs` run through the [`Scheduler`]
+    pub async fn esplora_scripthash_txs(
+        &self,
+        script: &ScriptBuf,
+        last_seen: Option<Txid>,
+    ) -> Result<Vec<Tx>, esplora_client::Error> {
+        let script_hash = sha256::Hash::hash(script.as_bytes());
+        match last_seen {
+            Some(last_seen) => self.get_json(&format!("/scripthash/{script_hash:x}/txs/chain/{last_seen}")).await,
+            None => self.get_json(&format!("/scripthash/{script_hash:x}/txs")).await,
+        }
+    }
+}
+
+#[allow(clippy::missing_errors_doc)]
+impl Client {
     /// Alternative to `esplora_client::AsyncClient::scripthash_txs`
     /// taking advantage of new mempool/electrs features
     pub async fn scripthash_txs(
@@ -25,63 +339,117 @@ impl Client {
         script: &ScriptBuf,
         last_seen: Option<Txid>,
         page_size: Option<usize>,
-    ) -> Result<Vec<Tx>, Error> {
+    ) -> Result<Vec<Tx>, esplora_client::Error> {
         let script_hash = sha256::Hash::hash(script.as_bytes());
         let max_txs = page_size.unwrap_or(50);
-        let url = last_seen.map_or_else(|| format!(
-                "{}/scripthash/{:x}/txs?max_txs={}",
-                self.client.url(),
-                script_hash,
-                max_txs
-            ), |after_txid| format!(
-                "{}/scripthash/{:x}/txs?max_txs={}&after_txid={}",
-                self.client.url(),
-                script_hash,
-                max_txs,
-                after_txid
-            ));
-        Ok(self
-            .client
-            .client()
-            .get(url)
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<Vec<Tx>>()
-            .await?)
+        let path = last_seen.map_or_else(
+            || format!("/scripthash/{script_hash:x}/txs?max_txs={max_txs}"),
+            |after_txid| format!("/scripthash/{script_hash:x}/txs?max_txs={max_txs}&after_txid={after_txid}"),
+        );
+        self.get_json(&path).await
+    }
+
+    /// Get the spend status of every output of a transaction, in output order
+    pub async fn get_tx_outspends(&self, txid: &Txid) -> Result<Vec<OutputStatus>, esplora_client::Error> {
+        self.get_json(&format!("/tx/{txid}/outspends")).await
     }
 
-    // TODO: make this interruptible
+    /// Get the confirmed and unconfirmed funding and spending totals
+    /// for the given scriptpubkey
+    pub async fn scripthash_stats(&self, script: &ScriptBuf) -> Result<AddressStats, esplora_client::Error> {
+        let script_hash = sha256::Hash::hash(script.as_bytes());
+        self.get_json(&format!("/scripthash/{script_hash:x}")).await
+    }
+
+
     /// Makes multiple requests to fetch the full transaction history
     /// of the given scriptpubkey using the REST API.
     ///
     /// Returns those transactions in chronological order (oldest first)
     ///
-    /// `until_txid` and `until_height` can be used to limit the number of API requests:
-    /// If either is provided, the function will only fetch as much history as necessary to find
+    /// `limit` can be used to limit the number of API requests:
+    /// If `until_txid` or `until_height` is provided, the function will only fetch
+    /// as much history as necessary to find
     ///  - a transaction with the given txid.
     ///  - a transaction confirmed at or below the given blockheight.
+    ///
+    /// If `max_txs` is provided, it stops as soon as that many transactions have been fetched.
+    ///
+    /// Pages are accumulated in `cursor`, so if the fetch fails or is interrupted
+    /// by `cancel`, calling this again with the same cursor resumes where it stopped.
+    ///
+    /// `on_progress` is called with the updated cursor after every page.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails, or [`Error::Cancelled`] if `cancel` is cancelled
     pub async fn fetch_address_history(
         &self,
         scriptpubkey: &ScriptBuf,
-        until_txid: Option<Txid>,
-        until_height: Option<u32>,
+        limit: HistoryLimit,
+        cursor: &mut HistoryCursor,
+        cancel: &CancellationToken,
+        on_progress: &(impl Fn(&HistoryCursor) + Sync),
     ) -> Result<Vec<Tx>, Error> {
-        let mut all_txs = Vec::new();
+        // the head of the history may have changed since an interrupted fetch began,
+        // so catch up on anything newer than the transactions we already have
+        let resumable = if let Some((resume_txid, head_limit)) = cursor.resume_point(limit) {
+            log::trace!("Resuming address history fetch after {:?}", cursor.after_txid);
+            let mut head = HistoryCursor::default();
+            self.fetch_history_pages(scriptpubkey, head_limit, &mut head, cancel, &|_| {})
+                .await?;
+            cursor.rebase(head, resume_txid)
+        } else {
+            true
+        };
+
+        if resumable {
+            self.fetch_history_pages(scriptpubkey, limit, cursor, cancel, on_progress)
+                .await?;
+        }
+
+        let mut all_txs = std::mem::take(cursor).fetched;
+        all_txs.reverse();
+        Ok(all_txs)
+    }
+
+    /// Fetches pages of history into `cursor` (newest first), continuing from
+    /// `cursor.after_txid` until the `limit` is reached
+    /// or the full history has been fetched.
+    async fn fetch_history_pages(
+        &self,
+        scriptpubkey: &ScriptBuf,
+        limit: HistoryLimit,
+        cursor: &mut HistoryCursor,
+        cancel: &CancellationToken,
+        on_progress: &(impl Fn(&HistoryCursor) + Sync),
+    ) -> Result<(), Error> {
+        let HistoryLimit { until_txid, until_height, max_txs } = limit;
         let mut done = false;
-        let mut found_txid = until_txid.is_none();
-        let mut found_height = until_height.is_none();
-        let limit_requests = !found_txid || !found_height;
-        let mut last_txid: Option<Txid> = None;
-
-        log::trace!(
-            "Fetching address history until {:?} / {:?}",
-            until_txid.clone(),
-            until_height.clone()
-        );
+        let mut found_txid = until_txid.is_none()
+            || cursor.fetched.iter().any(|tx| Some(tx.txid) == until_txid);
+        let mut found_height = until_height.is_none()
+            || cursor.fetched.last().is_some_and(|tx| {
+                tx.status.confirmed && tx.status.block_height < until_height
+            });
+        let limit_requests = until_txid.is_some() || until_height.is_some();
+
+        log::trace!("Fetching address history until {until_txid:?} / {until_height:?} / {max_txs:?}");
 
         while !done && (!limit_requests || !found_txid || !found_height) {
-            let mut txs = self.scripthash_txs(scriptpubkey, last_txid, None).await?;
+            if max_txs.is_some_and(|max_txs| cursor.fetched.len() >= max_txs) {
+                log::trace!("...fetched {} and reached the limit!", cursor.fetched.len());
+                break;
+            }
+
+            let mut txs = tokio::select! {
+                biased;
+                () = cancel.cancelled() => {
+                    log::trace!("...address history fetch cancelled at {:?}", cursor.after_txid);
+                    return Err(Error::Cancelled);
+                }
+                txs = self.scripthash_txs(scriptpubkey, cursor.after_txid, None) => txs?,
+            };
 
             found_txid |= limit_requests && txs.iter().any(|tx| Some(tx.txid) == until_txid);
 
@@ -92,21 +460,82 @@ impl Client {
             }
 
             if txs.len() == 50 {
-                last_txid = Some(txs.last().unwrap().txid);
+                cursor.after_txid = txs.last().map(|tx| tx.txid);
                 log::trace!(
                     "...fetched +{} = {} up to  {:?}",
                     txs.len(),
-                    all_txs.len(),
-                    last_txid
+                    cursor.fetched.len(),
+                    cursor.after_txid
                 );
             } else {
                 log::trace!("...fetched {} and done!", txs.len());
                 done = true;
             }
-            all_txs.append(&mut txs);
+            cursor.fetched.append(&mut txs);
+            cursor.pages_fetched += 1;
+            on_progress(cursor);
         }
 
-        all_txs.reverse();
-        Ok(all_txs)
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{funding, txid};
+
+    fn cursor(fetched: Vec<Tx>, after_txid: Option<Txid>) -> HistoryCursor {
+        HistoryCursor { after_txid, fetched, pages_fetched: 1 }
+    }
+
+    fn txids(cursor: &HistoryCursor) -> Vec<Txid> {
+        cursor.fetched.iter().map(|tx| tx.txid).collect()
+    }
+
+    /// A fetch interrupted after a page ending with 3, newest first
+    fn interrupted() -> HistoryCursor {
+        cursor(
+            vec![funding(5, 1_000, None), funding(4, 1_000, Some(103)), funding(3, 1_000, Some(102))],
+            Some(txid(3)),
+        )
+    }
+
+    #[test]
+    fn resumes_from_the_newest_confirmed_transaction() {
+        assert!(HistoryCursor::default().resume_point(HistoryLimit::default()).is_none());
+
+        let limit = HistoryLimit { until_height: Some(50), max_txs: Some(100), ..HistoryLimit::default() };
+        let (resume_txid, head_limit) = interrupted().resume_point(limit).unwrap();
+        assert_eq!(resume_txid, txid(4));
+        assert_eq!(head_limit.until_txid, Some(txid(4)));
+        assert_eq!(head_limit.until_height, None);
+        assert_eq!(head_limit.max_txs, Some(100));
+    }
+
+    #[test]
+    fn resume_replaces_the_stale_head() {
+        let mut cursor = interrupted();
+        // 5 confirmed and 6 arrived in the meantime
+        let head = self::cursor(
+            vec![funding(6, 1_000, None), funding(5, 1_000, Some(104)), funding(4, 1_000, Some(103))],
+            None,
+        );
+        assert!(cursor.rebase(head, txid(4)));
+        assert_eq!(txids(&cursor), [txid(6), txid(5), txid(4), txid(3)]);
+        assert!(cursor.fetched[1].status.confirmed);
+        assert_eq!(cursor.after_txid, Some(txid(3)));
+        assert_eq!(cursor.pages_fetched, 2);
+    }
+
+    #[test]
+    fn reorged_resume_point_starts_over() {
+        let mut cursor = interrupted();
+        // 4 was reorged out, so the refetched head runs to the end of the history
+        let head = self::cursor(vec![funding(7, 1_000, Some(103)), funding(3, 1_000, Some(102))], None);
+        assert!(!cursor.rebase(head, txid(4)));
+        assert_eq!(txids(&cursor), [txid(7), txid(3)]);
+        assert_eq!(cursor.after_txid, None);
+        assert_eq!(cursor.pages_fetched, 1);
     }
 }
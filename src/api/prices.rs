@@ -0,0 +1,75 @@
+//! Bitcoin exchange rates
+
+use std::collections::HashMap;
+
+use bitcoin::SignedAmount;
+use serde::{Deserialize, Serialize};
+
+use super::Client;
+
+/// Price of one bitcoin in each fiat currency, from `/v1/prices`
+/// or the websocket `conversions` payload
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Prices {
+    /// when the prices were fetched, in seconds since the epoch
+    pub time: u64,
+    /// keyed by currency code, e.g. `USD`
+    #[serde(flatten)]
+    pub rates: HashMap<String, f64>,
+}
+
+impl Prices {
+    /// Price of one bitcoin in `currency`, if the backend has one
+    #[must_use]
+    pub fn rate(&self, currency: &str) -> Option<f64> {
+        // the backend reports unavailable prices as -1
+        self.rates.get(currency).copied().filter(|rate| *rate > 0.0)
+    }
+
+    /// Value of `amount` in `currency`
+    #[must_use]
+    pub fn value(&self, amount: SignedAmount, currency: &str) -> Option<f64> {
+        self.rate(currency).map(|rate| amount.to_btc() * rate)
+    }
+}
+
+/// Past prices from `/v1/historical-price`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoricalPrices {
+    /// newest first
+    pub prices: Vec<Prices>,
+    /// rates between USD and other currencies, keyed like `USDEUR`
+    #[serde(default)]
+    pub exchange_rates: HashMap<String, f64>,
+}
+
+#[allow(clippy::missing_errors_doc)]
+impl Client {
+    /// Get the latest price of bitcoin in each supported currency
+    pub async fn get_prices(&self) -> Result<Prices, esplora_client::Error> {
+        self.get_json("/v1/prices").await
+    }
+
+    /// Get past prices of bitcoin, optionally only in `currency`
+    /// and only the closest price to `timestamp` (in seconds since the epoch)
+    pub async fn get_historical_price(
+        &self,
+        currency: Option<&str>,
+        timestamp: Option<u64>,
+    ) -> Result<HistoricalPrices, esplora_client::Error> {
+        let mut query = Vec::new();
+        if let Some(currency) = currency {
+            query.push(format!("currency={currency}"));
+        }
+        if let Some(timestamp) = timestamp {
+            query.push(format!("timestamp={timestamp}"));
+        }
+        let path = if query.is_empty() {
+            "/v1/historical-price".to_string()
+        } else {
+            format!("/v1/historical-price?{}", query.join("&"))
+        };
+        self.get_json(&path).await
+    }
+}
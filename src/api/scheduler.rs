@@ -0,0 +1,261 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::Error;
+use crate::compat::{self, Instant};
+
+/// Limits on how hard the REST API is used
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Sustained number of requests per second (must be positive, checked when the client is built)
+    pub requests_per_second: f64,
+    /// Number of requests which may be made at once above the sustained rate
+    pub burst: u32,
+    /// Number of times a failed request is retried before giving up
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each subsequent attempt
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between retries
+    pub max_backoff: Duration,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 10.0,
+            burst: 20,
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    paused_until: Instant,
+}
+
+/// Shared gatekeeper for every REST request made by a client
+///
+/// Requests are rate limited by a token bucket, and retried with exponential
+/// backoff if they fail with a 429, a 5xx or a timeout. A 429 pauses all
+/// requests, for as long as the server's `Retry-After` header asks if present.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    limits: RateLimit,
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+impl Scheduler {
+    pub fn new(limits: RateLimit) -> Result<Self, Error> {
+        if !(limits.requests_per_second.is_finite() && limits.requests_per_second > 0.0) {
+            return Err(Error::InvalidRateLimit(format!(
+                "requests_per_second must be positive, got {}",
+                limits.requests_per_second
+            )));
+        }
+        let now = Instant::now();
+        Ok(Self {
+            limits,
+            bucket: Arc::new(Mutex::new(TokenBucket {
+                tokens: f64::from(limits.burst),
+                last_refill: now,
+                paused_until: now,
+            })),
+        })
+    }
+
+    /// Send the request built by `request`, retrying on rate limits, server errors and timeouts
+    ///
+    /// Returns the last response received, which may still have an error status
+    pub async fn send(
+        &self,
+        request: impl Fn() -> RequestBuilder + Send + Sync,
+    ) -> Result<Response, reqwest::Error> {
+        let mut attempt = 0;
+        loop {
+            self.acquire().await;
+            match request().send().await {
+                Ok(response) if is_retryable_status(response.status()) && attempt < self.limits.max_retries => {
+                    let retry_after = parse_retry_after(&response);
+                    self.retry_later(response.status(), attempt, retry_after).await;
+                }
+                Err(e) if is_retryable_error(&e) && attempt < self.limits.max_retries => {
+                    self.retry_later(e.status().unwrap_or(StatusCode::REQUEST_TIMEOUT), attempt, None).await;
+                }
+                result => return result,
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Wait until the token bucket allows another request
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let now = Instant::now();
+                if bucket.paused_until > now {
+                    bucket.paused_until.saturating_duration_since(now)
+                } else {
+                    let elapsed = now.saturating_duration_since(bucket.last_refill).as_secs_f64();
+                    bucket.tokens = elapsed
+                        .mul_add(self.limits.requests_per_second, bucket.tokens)
+                        .min(f64::from(self.limits.burst.max(1)));
+                    bucket.last_refill = now;
+                    if bucket.tokens >= 1.0 {
+                        bucket.tokens -= 1.0;
+                        return;
+                    }
+                    Duration::from_secs_f64((1.0 - bucket.tokens) / self.limits.requests_per_second)
+                }
+            };
+            compat::sleep(as_millis(wait).max(1)).await;
+        }
+    }
+
+    /// Delay before retrying after `attempt` failed attempts, without a `Retry-After`
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.limits
+            .initial_backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.limits.max_backoff)
+    }
+
+    /// Wait before retrying a failed request.
+    ///
+    /// Rate limit responses pause every request sharing this scheduler,
+    /// other failures only delay the request which failed.
+    async fn retry_later(&self, status: StatusCode, attempt: u32, retry_after: Option<Duration>) {
+        let delay = retry_after.unwrap_or_else(|| self.backoff(attempt));
+        log::debug!("REST request failed with {status}, retrying in {delay:?} (attempt {attempt})");
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let mut bucket = self.bucket.lock().await;
+            bucket.paused_until = bucket.paused_until.max(Instant::now() + delay);
+        } else {
+            compat::sleep(as_millis(delay)).await;
+        }
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_retryable_error(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.status().is_some_and(is_retryable_status)
+}
+
+/// Parse a `Retry-After` header given in seconds
+fn parse_retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+fn as_millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(requests_per_second: f64, burst: u32) -> RateLimit {
+        RateLimit {
+            requests_per_second,
+            burst,
+            ..RateLimit::default()
+        }
+    }
+
+    #[test]
+    fn rejects_unenforceable_rates() {
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(Scheduler::new(limits(rate, 1)), Err(Error::InvalidRateLimit(_))));
+        }
+        assert!(Scheduler::new(limits(0.5, 0)).is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spaces_requests_after_the_burst() {
+        let scheduler = Scheduler::new(limits(4.0, 3)).unwrap();
+        let start = Instant::now();
+        for _ in 0..3 {
+            scheduler.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        scheduler.acquire().await;
+        assert_eq!(start.elapsed(), Duration::from_millis(250));
+        scheduler.acquire().await;
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+
+        // idle time refills the bucket, up to the burst size
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let resumed = Instant::now();
+        for _ in 0..3 {
+            scheduler.acquire().await;
+        }
+        assert_eq!(resumed.elapsed(), Duration::ZERO);
+        scheduler.acquire().await;
+        assert_eq!(resumed.elapsed(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let scheduler = Scheduler::new(RateLimit {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(3),
+            ..RateLimit::default()
+        })
+        .unwrap();
+        let delays: Vec<_> = (0..5).map(|attempt| scheduler.backoff(attempt)).collect();
+        assert_eq!(delays, [500, 1000, 2000, 3000, 3000].map(Duration::from_millis));
+        assert_eq!(scheduler.backoff(u32::MAX), Duration::from_secs(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limit_response_pauses_every_request() {
+        let scheduler = Scheduler::new(limits(10.0, 10)).unwrap();
+        let start = Instant::now();
+        scheduler
+            .retry_later(StatusCode::TOO_MANY_REQUESTS, 0, Some(Duration::from_secs(7)))
+            .await;
+        // the pause applies to the next acquire rather than the failed request alone
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        scheduler.acquire().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(7));
+
+        // without a Retry-After the pause falls back to the backoff
+        let start = Instant::now();
+        scheduler.retry_later(StatusCode::TOO_MANY_REQUESTS, 1, None).await;
+        scheduler.acquire().await;
+        assert_eq!(start.elapsed(), scheduler.backoff(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn server_errors_delay_only_the_failed_request() {
+        let scheduler = Scheduler::new(limits(10.0, 10)).unwrap();
+        let start = Instant::now();
+        scheduler.retry_later(StatusCode::SERVICE_UNAVAILABLE, 2, None).await;
+        assert_eq!(start.elapsed(), scheduler.backoff(2));
+        let resumed = Instant::now();
+        scheduler.acquire().await;
+        assert_eq!(resumed.elapsed(), Duration::ZERO);
+    }
+}
@@ -1,7 +1,12 @@
 use std::collections::HashMap;
 
-use crate::wallet::{Wallet, Options, Error as MwckError};
-use bitcoin::{Txid, Transaction, BlockHash, Block, MerkleBlock, ScriptBuf};
+use crate::api::broadcast::{PackageSubmission, Submission, TxResult};
+use crate::api::lightning::{ChannelClose, Channel, NetworkStatistics, Node, TxChannels};
+use crate::api::mining::{AuditSummary, BlockExtended, BlockFeeRates, BlockFees, BlockRewards, PoolHashrate, Pools, TimePeriod};
+use crate::api::mempool::{CpfpInfo, DifficultyAdjustment, MempoolBlock, MempoolInfo, RbfHistory, RecommendedFees};
+use crate::api::prices::{HistoricalPrices, Prices};
+use crate::wallet::{Wallet, Options, CancellationToken, Error as MwckError, RateLimit, DEFAULT_EVENT_JOURNAL_CAPACITY, DEFAULT_MAX_CONCURRENT_SYNCS, DEFAULT_RECONCILE_INTERVAL};
+use bitcoin::{Txid, Transaction, BlockHash, Block, MerkleBlock, OutPoint, ScriptBuf};
 use esplora_client::{Error, TxStatus, BlockStatus, MerkleProof, OutputStatus, Tx, BlockSummary};
 use reqwest;
 use delegate::delegate;
@@ -10,7 +15,7 @@ pub struct MempoolAsync {
     wallet: Wallet,
 }
 
-#[allow(clippy::inline_always)]
+#[allow(clippy::inline_always, clippy::missing_errors_doc)]
 impl MempoolAsync {
     #[must_use]
     pub fn new(options: &Options) -> Self {
@@ -30,22 +35,41 @@ impl MempoolAsync {
         self.wallet.connect(wait_for_connection).await
     }
 
+    pub async fn disconnect(&self, wait_for_close: bool) {
+        self.wallet.disconnect(wait_for_close).await;
+    }
+
     pub async fn mwck_scripthash_txs(
         &self,
         script: &ScriptBuf,
     ) -> Result<Vec<Tx>, MwckError> {
-        self.wallet.get_and_watch(script).await.map(|state| state.transactions)
+        self.wallet.get_and_watch(script).await.map(|state| {
+            state.transactions.iter().map(|tx| (**tx).clone()).collect()
+        })
     }
 
     pub async fn mwck_confirmed_scripthash_txs(
         &self,
         script: &ScriptBuf,
     ) -> Result<Vec<Tx>, MwckError> {
-        self.wallet.get_and_watch(script).await.map(|state| state.transactions.into_iter().filter(|tx| tx.status.confirmed).collect())
+        self.wallet.get_and_watch(script).await.map(|state| {
+            state.transactions.iter().filter(|tx| tx.status.confirmed).map(|tx| (**tx).clone()).collect()
+        })
+    }
+
+    /// Like [`MempoolAsync::mwck_scripthash_txs`], but without watching the scriptpubkey
+    pub async fn mwck_query_scripthash_txs(
+        &self,
+        script: &ScriptBuf,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Tx>, MwckError> {
+        self.wallet.query(script, cancel).await.map(|state| {
+            state.transactions.iter().map(|tx| (**tx).clone()).collect()
+        })
     }
 
     delegate! {
-        to self.wallet.api.client {
+        to self.wallet.api {
             /// Get a [`Transaction`] option given its [`Txid`]
             pub async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error>;
 
@@ -81,6 +105,24 @@ impl MempoolAsync {
                 index: u64,
             ) -> Result<Option<OutputStatus>, Error>;
 
+            /// Get the spending status of every output of a transaction, in output order
+            pub async fn get_tx_outspends(&self, txid: &Txid) -> Result<Vec<OutputStatus>, Error>;
+
+            /// Broadcast a [`Transaction`], returning the reason if the backend rejects it
+            ///
+            /// # Errors
+            ///
+            /// Returns an error if the request fails, rather than being answered with a rejection
+            pub async fn broadcast_tx(&self, tx: &Transaction) -> Result<Submission, Error>;
+
+            /// Check whether transactions would be accepted into the mempool, without broadcasting them
+            ///
+            /// Returns a result for each transaction, in the same order.
+            pub async fn test_mempool_accept(&self, txs: &[Transaction]) -> Result<Vec<TxResult>, Error>;
+
+            /// Submit a child and its unconfirmed parents, parents first, to be accepted into the mempool together
+            pub async fn broadcast_package(&self, txs: &[Transaction]) -> Result<PackageSubmission, Error>;
+
             /// Broadcast a [`Transaction`] to Esplora
             pub async fn broadcast(&self, transaction: &Transaction) -> Result<(), Error>;
 
@@ -96,6 +138,7 @@ impl MempoolAsync {
             /// Get confirmed transaction history for the specified address/scripthash,
             /// sorted with newest first. Returns 25 transactions per page.
             /// More can be requested by specifying the last txid seen by the previous query.
+            #[call(esplora_scripthash_txs)]
             pub async fn scripthash_txs(
                 &self,
                 script: &ScriptBuf,
@@ -112,6 +155,77 @@ impl MempoolAsync {
             /// while [mempool.space](https://mempool.space/docs/api) returns `15`.
             pub async fn get_blocks(&self, height: Option<u32>) -> Result<Vec<BlockSummary>, Error>;
 
+            /// Get the recommended feerates (in sat/vB) for a range of confirmation targets
+            pub async fn get_recommended_fees(&self) -> Result<RecommendedFees, Error>;
+
+            /// Get the blocks the backend expects to be mined next, from its current mempool
+            pub async fn get_mempool_blocks(&self) -> Result<Vec<MempoolBlock>, Error>;
+
+            /// Get the size and fee distribution of the mempool
+            pub async fn get_mempool_info(&self) -> Result<MempoolInfo, Error>;
+
+            /// Get the progress of the current difficulty epoch and the estimated next adjustment
+            pub async fn get_difficulty_adjustment(&self) -> Result<DifficultyAdjustment, Error>;
+
+            /// Get the unconfirmed ancestors and descendants of a transaction,
+            /// and the feerate it is effectively mined at as a result
+            pub async fn get_cpfp_info(&self, txid: &Txid) -> Result<CpfpInfo, Error>;
+
+            /// Get the transactions a transaction replaced, and any transactions which replaced it
+            pub async fn get_rbf_history(&self, txid: &Txid) -> Result<RbfHistory, Error>;
+
+            /// Get the latest price of bitcoin in each supported currency
+            pub async fn get_prices(&self) -> Result<Prices, Error>;
+
+            /// Get past prices of bitcoin, optionally only in `currency`
+            /// and only the closest price to `timestamp` (in seconds since the epoch)
+            pub async fn get_historical_price(
+                &self,
+                currency: Option<&str>,
+                timestamp: Option<u64>,
+            ) -> Result<HistoricalPrices, Error>;
+
+            /// Get a lightning node by its public key
+            pub async fn get_lightning_node(&self, public_key: &str) -> Result<Node, Error>;
+
+            /// Get a lightning channel by its id or short id
+            pub async fn get_lightning_channel(&self, id: &str) -> Result<Channel, Error>;
+
+            /// Get the channels opened and closed by each of `txids`, in the same order
+            pub async fn get_lightning_channels_by_txids(&self, txids: &[Txid]) -> Result<Vec<TxChannels>, Error>;
+
+            /// Get the latest statistics for the whole lightning network
+            pub async fn get_lightning_statistics(&self) -> Result<NetworkStatistics, Error>;
+
+            /// How the channel funded by `funding` was closed, if it has been closed on chain
+            pub async fn get_channel_close(&self, funding: &OutPoint) -> Result<Option<ChannelClose>, Error>;
+
+            /// Get the blocks mined by each pool over `period`
+            pub async fn get_mining_pools(&self, period: TimePeriod) -> Result<Pools, Error>;
+
+            /// Get the hashrate history of the pool identified by `slug`
+            pub async fn get_pool_hashrate(&self, slug: &str) -> Result<Vec<PoolHashrate>, Error>;
+
+            /// Get the hashrate history of every pool over `period`
+            pub async fn get_pools_hashrate(&self, period: TimePeriod) -> Result<Vec<PoolHashrate>, Error>;
+
+            /// Get the average block reward over `period`
+            pub async fn get_block_rewards(&self, period: TimePeriod) -> Result<Vec<BlockRewards>, Error>;
+
+            /// Get the average total fees per block over `period`
+            pub async fn get_block_fees(&self, period: TimePeriod) -> Result<Vec<BlockFees>, Error>;
+
+            /// Get percentiles of the feerates paid in blocks over `period`
+            pub async fn get_block_fee_rates(&self, period: TimePeriod) -> Result<Vec<BlockFeeRates>, Error>;
+
+            /// Get a block along with the pool which mined it and its rewards
+            pub async fn get_block_extended(&self, block_hash: &BlockHash) -> Result<BlockExtended, Error>;
+
+            /// Get how a block compared to the template the backend expected
+            pub async fn get_block_audit_summary(&self, block_hash: &BlockHash) -> Result<Option<AuditSummary>, Error>;
+        }
+
+        to self.wallet.api.client {
             /// Get the underlying base URL.
             pub fn url(&self) -> &str;
 
@@ -143,5 +257,11 @@ fn url_to_options(input: &str) -> Result<Options, &'static str> {
     Ok(Options {
         hostname: host.to_string(),
         secure: scheme == "https",
+        max_concurrent_syncs: DEFAULT_MAX_CONCURRENT_SYNCS,
+        rate_limit: RateLimit::default(),
+        event_journal_capacity: DEFAULT_EVENT_JOURNAL_CAPACITY,
+        saved_journal: None,
+        reconcile_interval: DEFAULT_RECONCILE_INTERVAL,
+        track_prices: false,
     })
 }
\ No newline at end of file
@@ -19,6 +19,39 @@ where
   None
 }
 
+/// Spawn a task, calling `on_panic` with a description of the panic if it panics
+#[cfg(not(target_arch = "wasm32"))]
+pub fn spawn_supervised<F, P>(future: F, on_panic: P)
+where
+    F: Future<Output = ()> + Send + 'static,
+    P: FnOnce(String) + Send + 'static,
+{
+    let handle = tokio::task::spawn(future);
+    tokio::task::spawn(async move {
+        if let Err(err) = handle.await {
+            if err.is_panic() {
+                on_panic(err.to_string());
+            }
+        }
+    });
+}
+
+/// Spawn a task (panics abort on wasm, so there is nothing to supervise)
+#[cfg(target_arch = "wasm32")]
+pub fn spawn_supervised<F, P>(future: F, _on_panic: P)
+where
+    F: Future<Output = ()> + 'static,
+    P: FnOnce(String) + 'static,
+{
+  wasm_bindgen_futures::spawn_local(future);
+}
+
+/// A monotonic clock, which tokio's test clock can pause and advance
+#[cfg(not(target_arch = "wasm32"))]
+pub use tokio::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub use instant::Instant;
+
 #[must_use]
 pub fn now() -> Duration {
   #[cfg(target_arch = "wasm32")]
@@ -2,9 +2,12 @@
 #![warn(clippy::pedantic)]
 #![warn(clippy::nursery)]
 
-mod api;
+pub mod api;
 mod socket;
 mod compat;
+mod cancel;
 pub mod wallet;
 pub mod async_client;
+#[cfg(test)]
+mod test_utils;
 pub use async_client::MempoolAsync;
\ No newline at end of file
@@ -7,7 +7,7 @@ use super::native::connect;
 use super::wasm::connect;
 
 use bitcoin::ScriptBuf;
-use tokio::sync::{broadcast, oneshot, RwLock};
+use tokio::sync::{broadcast, oneshot, watch, RwLock};
 use tokio::task::JoinHandle;
 
 use crate::compat;
@@ -47,6 +47,8 @@ pub struct Manager {
     status_sender: broadcast::Sender<Status>,
     event_sender: broadcast::Sender<WebsocketEvent>,
     control_sender: broadcast::Sender<Event>,
+    /// whether the connection should be kept alive, shared by every clone
+    running: Arc<watch::Sender<bool>>,
 }
 
 impl Manager {
@@ -58,11 +60,13 @@ impl Manager {
         let (event_sender, _) = broadcast::channel(256);
         // TODO: replace the control broadcast channel with an intermediated mpsc channel?
         let (control_sender, _) = broadcast::channel(256);
+        let (running, _) = watch::channel(false);
         Self {
             ws_url,
             status_sender,
             event_sender,
             control_sender,
+            running: Arc::new(running),
         }
     }
 
@@ -86,7 +90,29 @@ impl Manager {
         log::trace!("sent Unsubscribe control event, result: {:?}", result);
     }
 
+    pub fn request_init_data(&self) {
+        log::trace!("connection request_init_data");
+        let result = self.control_sender.send(Event::Init);
+        log::trace!("sent Init control event, result: {result:?}");
+    }
+
+    /// Marks the connection as wanted, returning false if it already was
+    pub fn claim(&self) -> bool {
+        !self.running.send_replace(true)
+    }
+
+    /// Give up on a connection whose state machine has died,
+    /// so that anything waiting on it can move on
+    pub fn abandon(&self) {
+        self.running.send_replace(false);
+        self.notify(WebsocketEvent::Error);
+        let _ = self.status_sender.send(Status::Offline);
+        self.notify(WebsocketEvent::Offline);
+    }
+
     /// Executes a state machine to manage the websocket connection
+    ///
+    /// Should only be run after a successful [`Manager::claim`]
     pub async fn start(&mut self) {
         log::trace!("connection start");
         let mut status = StatusUpdater {
@@ -102,19 +128,18 @@ impl Manager {
             match status.get() {
                 // Offline => exit
                 Status::Offline => {
-                    log::trace!("waiting for threads to exit");
-                    if let Some(handles) = handles.take() {
-                        for handle in handles.into_iter().flatten() {
-                            handle.await.expect("websocket thread failed");
-                        }
-                    }
-                    log::trace!("joined loop threads");
+                    self.join_threads(handles.take()).await;
                     break
                 }
-                // Ready => Connecting
+                // Ready => Connecting | Offline
                 Status::Ready => {
-                    log::trace!("ready => connecting");
-                    status.update(Status::Connecting);
+                    if self.is_running() {
+                        log::trace!("ready => connecting");
+                        status.update(Status::Connecting);
+                    } else {
+                        log::trace!("ready => offline");
+                        status.update(Status::Offline);
+                    }
                 }
                 // Connecting => Connected | Disconnected
                 Status::Connecting => {
@@ -135,16 +160,15 @@ impl Manager {
                 },
                 // Disconnected => Ready (delayed to rate-limit reconnections)
                 Status::Disconnected => {
-                    log::trace!("waiting for threads to exit");
-                    if let Some(handles) = handles.take() {
-                        for handle in handles.into_iter().flatten() {
-                            handle.await.expect("websocket thread failed");
+                    self.join_threads(handles.take()).await;
+                    self.notify(WebsocketEvent::Disconnected);
+                    log::trace!("reconnecting in 30 seconds");
+                    tokio::select! {
+                        () = compat::sleep(30_000) => {}
+                        () = self.stopped() => {
+                            log::trace!("stopped while waiting to reconnect");
                         }
                     }
-                    log::trace!("joined loop threads");
-                    self.notify(WebsocketEvent::Disconnected);
-                    log::trace!("reconnecting in 60 seconds");
-                    compat::sleep(30_000).await;
                     status.update(Status::Ready);
                 }
                 // Connected => steady state until CLOSE or ERROR
@@ -152,18 +176,24 @@ impl Manager {
                     let mut close_signal = close_receiver.take().expect("can never reach a Connected state without (re)initializing the close channel");
                     let disconnect_sender = disconnect_channel.take().expect("can never reach a Connected state without (re)initializing the disconnect channel");
                     let mut disconnect_receiver = disconnect_sender.subscribe();
-                    tokio::select! {
+                    let stop_requested = tokio::select! {
                         // Connected => Disconnected
                         _ = disconnect_receiver.recv() => {
                             log::trace!("event or control thread exited");
                             status.update(Status::Disconnected);
+                            false
                         }
-                        
+
+                        () = self.stopped() => true,
+
                         // Connected => Offline
                         close_event = &mut close_signal => {
                             match close_event {
                                 Ok(_) => {
                                     log::trace!("received request to close connection");
+                                    // not going through stop(), so the connection must be released here
+                                    // for a later claim() to succeed
+                                    self.running.send_replace(false);
                                     status.update(Status::Offline);
                                 }
 
@@ -174,7 +204,18 @@ impl Manager {
                             }
                             // tell threads to exit
                             let _ = disconnect_sender.send(true);
+                            false
                         }
+                    };
+
+                    // Connected => Offline
+                    if stop_requested {
+                        log::trace!("received request to close connection");
+                        let _ = self.control_sender.send(Event::Close);
+                        // resolves once the close frame is sent, or the control thread exits
+                        let _ = close_signal.await;
+                        status.update(Status::Offline);
+                        let _ = disconnect_sender.send(true);
                     }
                 }
             }
@@ -185,9 +226,12 @@ impl Manager {
 
     pub async fn stop(&self) {
         log::trace!("stopping connection");
-        let _ = self.control_sender.send(Event::Close);
-        // wait for websocket to finish closing
         let mut rx = self.status_sender.subscribe();
+        if !self.running.send_replace(false) {
+            log::trace!("connection was not running");
+            return;
+        }
+        // wait for websocket to finish closing
         while let Ok(status) = rx.recv().await {
             if status == Status::Offline {
                 log::trace!("connection closed!");
@@ -264,6 +308,32 @@ impl Manager {
         }
     }
 
+    fn is_running(&self) -> bool {
+        *self.running.borrow()
+    }
+
+    /// Resolves once the connection is no longer wanted
+    async fn stopped(&self) {
+        let mut running = self.running.subscribe();
+        while *running.borrow_and_update() {
+            if running.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Wait for a connection's threads to exit, reporting any which panicked
+    async fn join_threads(&self, handles: Option<Vec<Option<JoinHandle<()>>>>) {
+        log::trace!("waiting for threads to exit");
+        for handle in handles.into_iter().flatten().flatten() {
+            if let Err(err) = handle.await {
+                log::error!("websocket thread failed: {err}");
+                self.notify(WebsocketEvent::Error);
+            }
+        }
+        log::trace!("joined loop threads");
+    }
+
     fn notify(&self, event: WebsocketEvent) {
         let _ = self.event_sender.send(event);
     }
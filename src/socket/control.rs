@@ -13,6 +13,8 @@ use esplora_client::ScriptBuf;
 pub enum Event {
     Close,
     Ping,
+    /// ask for the initial data, which includes the latest exchange rates
+    Init,
     Subscribe(Vec<ScriptBuf>),
     Unsubscribe(Vec<ScriptBuf>),
 }
@@ -75,6 +77,11 @@ impl Manager {
                             let message = "{\"action\": \"ping\"}".to_string();
                             let _ = self.ws_tx.send(Message::Text(message)).await;
                         }
+                        Event::Init => {
+                            log::trace!("websocket init data requested {id}");
+                            let message = "{\"action\": \"init\"}".to_string();
+                            let _ = self.ws_tx.send(Message::Text(message)).await;
+                        }
                         Event::Subscribe(scriptpubkeys) => {
                             log::trace!("control subscribing to new addresses {:?} {}", scriptpubkeys, id);
                             let mut changed = false;
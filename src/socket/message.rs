@@ -1,3 +1,4 @@
+use crate::api::prices::Prices;
 use crate::compat;
 use crate::wallet::address::Event as AddressEvent;
 
@@ -11,12 +12,15 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{broadcast, RwLock};
 use futures_util::StreamExt;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use esplora_client::{ScriptBuf, Tx};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum WebsocketEvent {
     AddressEvent(AddressEvent),
+    /// the latest exchange rates
+    Conversions(Prices),
     Offline,
     Disconnected,
     Connected,
@@ -27,6 +31,8 @@ pub enum WebsocketEvent {
 struct WebsocketResponse {
     #[serde(rename = "multi-scriptpubkey-transactions")]
     multi_scriptpubkey_transactions: Option<HashMap<ScriptBuf, WebsocketAddressTransactions>>,
+    // parsed separately, so a malformed price can't cost us address updates
+    conversions: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize)]
@@ -106,6 +112,15 @@ impl Manager {
                     log::trace!("broadcasting multi-spk transactions event");
                     self.notify_spk_transactions(&payload);
                 }
+                if let Some(conversions) = message.conversions {
+                    match serde_json::from_value::<Prices>(conversions) {
+                        Ok(prices) => {
+                            log::trace!("broadcasting conversions event");
+                            let _ = self.event_sender.send(WebsocketEvent::Conversions(prices));
+                        }
+                        Err(e) => log::warn!("failed to parse websocket conversions {e:?}"),
+                    }
+                }
             }
             Err(e) => {
                 log::error!("failed to parse websocket response {:?}", e);
@@ -32,17 +32,32 @@ impl Client {
 
     /// Connect to the websocket and keep it alive
     /// resolves the first time the websocket successfully connects
+    ///
+    /// Does nothing if the websocket is already running
     pub async fn start(&self, wait_for_connection: bool) {
         log::trace!("starting websocket");
+        // receivers only see what is sent after they subscribe,
+        // so subscribe before starting, or the first connection could be missed
+        let mut rx = self.manager.subscribe_to_status();
+        if !self.manager.claim() {
+            log::trace!("websocket is already running");
+            return;
+        }
         log::trace!("spawning thread to start connection");
         let mut manager = self.manager.clone();
-        compat::spawn(async move {
-            manager.start().await;
-        });
+        let failed_manager = self.manager.clone();
+        compat::spawn_supervised(
+            async move {
+                manager.start().await;
+            },
+            move |error| {
+                log::error!("websocket connection manager failed: {error}");
+                failed_manager.abandon();
+            },
+        );
 
         log::trace!("waiting for socket to finish trying to connect");
         if wait_for_connection {
-            let mut rx = self.manager.subscribe_to_status();
             loop {
                 let event = rx.recv().await;
                 if let Ok(Status::Connected | Status::Offline) = event {
@@ -58,11 +73,15 @@ impl Client {
     /// Disconnect the websocket and stop trying to reconnect
     /// resolves once all websocket handling threads have been cleaned up
     pub async fn stop(&self, wait_for_close: bool) {
-        log::trace!("starting websocket");
-        let fut = self.manager.stop();
+        log::trace!("stopping websocket");
         if wait_for_close {
             log::trace!("waiting for websocket to close");
-            fut.await;
+            self.manager.stop().await;
+        } else {
+            let manager = self.manager.clone();
+            compat::spawn(async move {
+                manager.stop().await;
+            });
         }
         log::trace!("returning from socket::stop");
     }
@@ -80,4 +99,10 @@ impl Client {
         log::trace!("socket untrack_scriptpubkeys");
         self.manager.untrack_scriptpubkeys(scriptpubkeys.to_vec());
     }
+
+    /// Ask for the initial data, which includes the latest exchange rates
+    pub fn request_init_data(&self) {
+        log::trace!("socket request_init_data");
+        self.manager.request_init_data();
+    }
 }
@@ -0,0 +1,62 @@
+//! Transactions and scriptpubkeys shared by the unit tests
+
+use bitcoin::hashes::Hash;
+use bitcoin::{ScriptBuf, Txid};
+use esplora_client::{PrevOut, Tx, TxStatus, Vin, Vout};
+
+/// The scriptpubkey of our address
+pub fn spk() -> ScriptBuf {
+    ScriptBuf::from(vec![0x51])
+}
+
+/// A scriptpubkey of someone else
+pub fn theirs() -> ScriptBuf {
+    ScriptBuf::from(vec![0x52])
+}
+
+pub fn txid(n: u8) -> Txid {
+    Txid::from_byte_array([n; 32])
+}
+
+/// Confirmed at `height`, or unconfirmed
+pub const fn status(height: Option<u32>) -> TxStatus {
+    TxStatus {
+        confirmed: height.is_some(),
+        block_height: height,
+        block_hash: None,
+        block_time: None,
+    }
+}
+
+/// A transaction `n` spending `inputs` of (funding txid, scriptpubkey, value),
+/// paying `outputs` of (scriptpubkey, value)
+pub fn tx(n: u8, height: Option<u32>, inputs: &[(u8, ScriptBuf, u64)], outputs: &[(ScriptBuf, u64)]) -> Tx {
+    Tx {
+        txid: txid(n),
+        version: 2,
+        locktime: 0,
+        vin: inputs
+            .iter()
+            .map(|(funding, scriptpubkey, value)| Vin {
+                txid: txid(*funding),
+                vout: 0,
+                prevout: Some(PrevOut { value: *value, scriptpubkey: scriptpubkey.clone() }),
+                scriptsig: ScriptBuf::new(),
+                witness: Vec::new(),
+                sequence: u32::MAX,
+                is_coinbase: false,
+            })
+            .collect(),
+        vout: outputs
+            .iter()
+            .map(|(scriptpubkey, value)| Vout { value: *value, scriptpubkey: scriptpubkey.clone() })
+            .collect(),
+        status: status(height),
+        fee: 0,
+    }
+}
+
+/// A transaction paying `value` to [`spk`]
+pub fn funding(n: u8, value: u64, height: Option<u32>) -> Tx {
+    tx(n, height, &[], &[(spk(), value)])
+}
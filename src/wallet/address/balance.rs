@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use bitcoin::amount::serde::as_sat;
+use bitcoin::{Amount, SignedAmount, Txid};
+use esplora_client::{ScriptBuf, Tx};
+use serde::{Deserialize, Serialize};
+
+/// Confirmations a coinbase output needs before it can be spent
+pub const COINBASE_MATURITY: u32 = 100;
+
+/// Balance of an address or wallet, split by how safe it is to spend
+///
+/// Value spent from confirmed outputs by unconfirmed transactions is counted in
+/// `outgoing_pending`, and stays in `confirmed` until the spend confirms. Value spent
+/// from unconfirmed outputs is taken straight off the pending amount it was received in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balance {
+    /// confirmed, and spendable
+    #[serde(with = "as_sat")]
+    pub confirmed: Amount,
+    /// unconfirmed outputs of transactions which only spend our own confirmed
+    /// or trusted coins, e.g. change
+    #[serde(with = "as_sat")]
+    pub trusted_pending: Amount,
+    /// unconfirmed outputs of transactions sent to us by others,
+    /// or which spend our own untrusted coins
+    #[serde(with = "as_sat")]
+    pub untrusted_pending: Amount,
+    /// confirmed value being spent by unconfirmed transactions
+    #[serde(with = "as_sat")]
+    pub outgoing_pending: Amount,
+    /// confirmed coinbase outputs which have not yet reached maturity
+    #[serde(with = "as_sat")]
+    pub immature: Amount,
+}
+
+impl Balance {
+    /// What can be spent without relying on anyone else's unconfirmed transactions
+    #[must_use]
+    pub fn trusted_spendable(&self) -> SignedAmount {
+        signed(self.confirmed + self.trusted_pending) - signed(self.outgoing_pending)
+    }
+
+    /// Everything received, minus everything spent, confirmed or not
+    #[must_use]
+    pub fn total(&self) -> SignedAmount {
+        signed(self.confirmed + self.trusted_pending + self.untrusted_pending + self.immature)
+            - signed(self.outgoing_pending)
+    }
+
+    /// Add the effect of the unconfirmed transactions among `txs`,
+    /// where `is_ours` says which scriptpubkeys are ours
+    ///
+    /// `txs` must include every unconfirmed transaction funding an output of ours
+    /// which they spend, anything else is assumed to be confirmed.
+    pub(crate) fn add_pending<'a>(
+        &mut self,
+        txs: impl IntoIterator<Item = &'a Tx>,
+        is_ours: impl Fn(&ScriptBuf) -> bool,
+    ) {
+        let pending: HashMap<Txid, &Tx> = txs
+            .into_iter()
+            .filter(|tx| !tx.status.confirmed)
+            .map(|tx| (tx.txid, tx))
+            .collect();
+        let mut trust = Trust {
+            pending: &pending,
+            is_ours: &is_ours,
+            trusted: HashMap::new(),
+        };
+
+        let mut trusted = Amount::ZERO;
+        let mut untrusted = Amount::ZERO;
+        let mut spent_trusted = Amount::ZERO;
+        let mut spent_untrusted = Amount::ZERO;
+        for tx in pending.values() {
+            let (funded, _) = flows(tx, &is_ours);
+            if trust.is_trusted(tx) {
+                trusted += funded;
+            } else {
+                untrusted += funded;
+            }
+            for vin in &tx.vin {
+                let Some(prevout) = vin.prevout.as_ref().filter(|prevout| is_ours(&prevout.scriptpubkey)) else {
+                    continue;
+                };
+                let value = Amount::from_sat(prevout.value);
+                match pending.get(&vin.txid) {
+                    None => self.outgoing_pending += value,
+                    Some(parent) if trust.is_trusted(parent) => spent_trusted += value,
+                    Some(_) => spent_untrusted += value,
+                }
+            }
+        }
+        self.trusted_pending += trusted.checked_sub(spent_trusted).unwrap_or(Amount::ZERO);
+        self.untrusted_pending += untrusted.checked_sub(spent_untrusted).unwrap_or(Amount::ZERO);
+    }
+}
+
+/// Whether unconfirmed transactions are trusted, i.e. spend our own coins,
+/// and only confirmed ones or the outputs of other trusted transactions
+struct Trust<'a, F> {
+    pending: &'a HashMap<Txid, &'a Tx>,
+    is_ours: &'a F,
+    trusted: HashMap<Txid, bool>,
+}
+
+impl<F: Fn(&ScriptBuf) -> bool> Trust<'_, F> {
+    fn is_trusted(&mut self, tx: &Tx) -> bool {
+        if let Some(trusted) = self.trusted.get(&tx.txid) {
+            return *trusted;
+        }
+        // provisionally untrusted, in case of a cycle
+        self.trusted.insert(tx.txid, false);
+        let mut spends_ours = false;
+        let mut trusted = true;
+        for vin in &tx.vin {
+            if !vin.prevout.as_ref().is_some_and(|prevout| (self.is_ours)(&prevout.scriptpubkey)) {
+                continue;
+            }
+            spends_ours = true;
+            if let Some(parent) = self.pending.get(&vin.txid).copied() {
+                trusted &= self.is_trusted(parent);
+            }
+        }
+        let trusted = spends_ours && trusted;
+        self.trusted.insert(tx.txid, trusted);
+        trusted
+    }
+}
+
+/// Value paid to, and value spent from, the scriptpubkeys for which `is_ours` is true
+pub fn flows(tx: &Tx, is_ours: impl Fn(&ScriptBuf) -> bool) -> (Amount, Amount) {
+    let spent = tx
+        .vin
+        .iter()
+        .filter_map(|vin| vin.prevout.as_ref())
+        .filter(|prevout| is_ours(&prevout.scriptpubkey))
+        .map(|prevout| Amount::from_sat(prevout.value))
+        .sum();
+    let funded = tx
+        .vout
+        .iter()
+        .filter(|vout| is_ours(&vout.scriptpubkey))
+        .map(|vout| Amount::from_sat(vout.value))
+        .sum();
+    (funded, spent)
+}
+
+/// Whether `tx` is a confirmed coinbase transaction which has not yet matured,
+/// given the height of the best block seen so far
+pub fn is_immature(tx: &Tx, tip: Option<u32>) -> bool {
+    if !tx.status.confirmed || !tx.vin.first().is_some_and(|vin| vin.is_coinbase) {
+        return false;
+    }
+    tx.status.block_height.is_some_and(|height| {
+        let confirmations = tip.unwrap_or(height).max(height) - height + 1;
+        confirmations < COINBASE_MATURITY
+    })
+}
+
+pub fn signed(amount: Amount) -> SignedAmount {
+    SignedAmount::from_sat(i64::try_from(amount.to_sat()).unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use esplora_client::Vin;
+
+    use super::*;
+    use crate::test_utils::{spk, theirs, tx};
+
+    fn pending(txs: &[Tx], confirmed: u64) -> Balance {
+        let mut balance = Balance {
+            confirmed: Amount::from_sat(confirmed),
+            ..Balance::default()
+        };
+        balance.add_pending(txs, |scriptpubkey| scriptpubkey == &spk());
+        balance
+    }
+
+    #[test]
+    fn receipts_from_others_are_untrusted() {
+        let balance = pending(&[tx(1, None, &[(9, theirs(), 5_000)], &[(spk(), 4_000)])], 0);
+        assert_eq!(balance.untrusted_pending, Amount::from_sat(4_000));
+        assert_eq!(balance.trusted_spendable(), SignedAmount::ZERO);
+        assert_eq!(balance.total(), SignedAmount::from_sat(4_000));
+    }
+
+    #[test]
+    fn change_from_confirmed_coins_is_trusted() {
+        let spend = tx(2, None, &[(1, spk(), 10_000)], &[(theirs(), 6_000), (spk(), 3_000)]);
+        let balance = pending(&[tx(1, Some(100), &[], &[(spk(), 10_000)]), spend], 10_000);
+        assert_eq!(balance.outgoing_pending, Amount::from_sat(10_000));
+        assert_eq!(balance.trusted_pending, Amount::from_sat(3_000));
+        assert_eq!(balance.trusted_spendable(), SignedAmount::from_sat(3_000));
+        assert_eq!(balance.total(), SignedAmount::from_sat(3_000));
+    }
+
+    #[test]
+    fn spending_untrusted_coins_never_goes_negative() {
+        let receipt = tx(1, None, &[(9, theirs(), 5_000)], &[(spk(), 4_000)]);
+        let spend = tx(2, None, &[(1, spk(), 4_000)], &[(theirs(), 1_000), (spk(), 2_500)]);
+        let balance = pending(&[spend, receipt], 0);
+        assert_eq!(balance.outgoing_pending, Amount::ZERO);
+        // the change is no safer than the coins it came from
+        assert_eq!(balance.trusted_pending, Amount::ZERO);
+        assert_eq!(balance.untrusted_pending, Amount::from_sat(2_500));
+        assert_eq!(balance.trusted_spendable(), SignedAmount::ZERO);
+        assert_eq!(balance.total(), SignedAmount::from_sat(2_500));
+    }
+
+    #[test]
+    fn chained_change_stays_trusted() {
+        let first = tx(2, None, &[(1, spk(), 10_000)], &[(theirs(), 2_000), (spk(), 7_000)]);
+        let second = tx(3, None, &[(2, spk(), 7_000)], &[(theirs(), 2_000), (spk(), 4_000)]);
+        let balance = pending(&[second, first], 10_000);
+        assert_eq!(balance.outgoing_pending, Amount::from_sat(10_000));
+        assert_eq!(balance.trusted_pending, Amount::from_sat(4_000));
+        assert_eq!(balance.untrusted_pending, Amount::ZERO);
+        assert_eq!(balance.trusted_spendable(), SignedAmount::from_sat(4_000));
+    }
+
+    #[test]
+    fn mixing_untrusted_coins_taints_the_change() {
+        let receipt = tx(1, None, &[(9, theirs(), 5_000)], &[(spk(), 4_000)]);
+        let spend = tx(2, None, &[(1, spk(), 4_000), (8, spk(), 6_000)], &[(theirs(), 7_000), (spk(), 2_000)]);
+        let balance = pending(&[receipt, spend], 6_000);
+        assert_eq!(balance.outgoing_pending, Amount::from_sat(6_000));
+        assert_eq!(balance.trusted_pending, Amount::ZERO);
+        assert_eq!(balance.untrusted_pending, Amount::from_sat(2_000));
+        assert_eq!(balance.trusted_spendable(), SignedAmount::ZERO);
+        assert_eq!(balance.total(), SignedAmount::from_sat(2_000));
+    }
+
+    #[test]
+    fn coinbase_matures_after_100_blocks() {
+        let mut coinbase = tx(1, Some(100), &[], &[(spk(), 50_000)]);
+        coinbase.vin.push(Vin {
+            txid: Txid::all_zeros(),
+            vout: u32::MAX,
+            prevout: None,
+            scriptsig: ScriptBuf::new(),
+            witness: Vec::new(),
+            sequence: u32::MAX,
+            is_coinbase: true,
+        });
+        assert!(is_immature(&coinbase, None));
+        assert!(is_immature(&coinbase, Some(100 + COINBASE_MATURITY - 2)));
+        assert!(!is_immature(&coinbase, Some(100 + COINBASE_MATURITY - 1)));
+        assert!(!is_immature(&tx(2, Some(100), &[], &[(spk(), 1)]), Some(100)));
+    }
+}
@@ -1,20 +1,37 @@
 use std::{
     cmp::Ordering,
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
 };
 
-use bitcoin::Txid;
+use bitcoin::{Amount, SignedAmount, Txid};
 use esplora_client::{ScriptBuf, Tx};
-use serde::Serialize;
-use tokio::sync::broadcast;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 
 use super::Event as WalletEvent;
+use super::journal::EventJournal;
+use super::schema;
+use super::store::TxStore;
+use super::tip::ChainTip;
+use crate::api::mempool::CpfpInfo;
+use crate::api::{AddressStats, HistoryCursor, HistoryLimit, TxoStats};
+use crate::cancel::CancellationToken;
 
-#[derive(Debug, Clone)]
+/// Most realtime events queued for an address waiting to (re)sync,
+/// beyond which they are dropped and the address is fully resynced instead
+const MAX_QUEUED_EVENTS: usize = 1_000;
+
+mod balance;
+pub use balance::{Balance, COINBASE_MATURITY};
+pub(crate) use balance::{flows, signed};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum Event {
-    Removed(ScriptBuf, Tx),
-    Mempool(ScriptBuf, Tx),
-    Confirmed(ScriptBuf, Tx),
+    Removed(ScriptBuf, #[serde(with = "schema::tx")] Tx),
+    Mempool(ScriptBuf, #[serde(with = "schema::tx")] Tx),
+    Confirmed(ScriptBuf, #[serde(with = "schema::tx")] Tx),
 }
 
 impl std::fmt::Display for Event {
@@ -33,70 +50,149 @@ impl std::fmt::Display for Event {
     }
 }
 
-#[derive(Debug, Default, Clone, Serialize)]
-pub struct Balance {
-    pub funded: u64,
-    pub spent: u64,
-}
+impl Event {
+    #[must_use]
+    pub const fn scriptpubkey(&self) -> &ScriptBuf {
+        match self {
+            Self::Removed(scriptpubkey, _) | Self::Mempool(scriptpubkey, _) | Self::Confirmed(scriptpubkey, _) => {
+                scriptpubkey
+            }
+        }
+    }
 
-impl Balance {
     #[must_use]
-    const fn new() -> Self {
-        Self {
-            funded: 0,
-            spent: 0,
+    pub const fn tx(&self) -> &Tx {
+        match self {
+            Self::Removed(_, tx) | Self::Mempool(_, tx) | Self::Confirmed(_, tx) => tx,
         }
     }
-}
 
-impl Balance {
+    /// Value received by the address minus value spent from it by the transaction
     #[must_use]
-    const fn get(self) -> i64 {
-        if self.funded >= self.spent {
-            (self.funded - self.spent) as i64
-        } else {
-            -((self.spent - self.funded) as i64)
+    pub fn value(&self) -> SignedAmount {
+        let (funded, spent) = balance::flows(self.tx(), |spk| spk == self.scriptpubkey());
+        balance::signed(funded) - balance::signed(spent)
+    }
+}
+
+/// What a transaction contributes to the REST address summary of `scriptpubkey`
+fn txo_stats(tx: &Tx, scriptpubkey: &ScriptBuf) -> TxoStats {
+    let mut stats = TxoStats {
+        tx_count: 1,
+        ..TxoStats::default()
+    };
+    for vout in tx.vout.iter().filter(|vout| &vout.scriptpubkey == scriptpubkey) {
+        stats.funded_txo_count += 1;
+        stats.funded_txo_sum += vout.value;
+    }
+    for prevout in tx.vin.iter().filter_map(|vin| vin.prevout.as_ref()) {
+        if &prevout.scriptpubkey == scriptpubkey {
+            stats.spent_txo_count += 1;
+            stats.spent_txo_sum += prevout.value;
         }
     }
+    stats
 }
 
-#[derive(Debug, Default, Clone, Serialize)]
-pub struct Balances {
-    pub mempool: Balance,
-    pub confirmed: Balance,
+/// How much of an address history to fetch and keep in memory
+///
+/// Balances of addresses with a bounded window are taken from the
+/// REST address summary rather than summed from their transactions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum HistoryWindow {
+    #[default]
+    Full,
+    /// only the most recent `n` transactions, plus any unconfirmed ones beyond that
+    Recent(usize),
+    /// only unconfirmed transactions and those confirmed at or above this height
+    Since(u32),
 }
 
-impl Balances {
-    const fn new() -> Self {
-        Self {
-            mempool: Balance::new(),
-            confirmed: Balance::new(),
-        }
+impl HistoryWindow {
+    #[must_use]
+    pub const fn is_bounded(&self) -> bool {
+        !matches!(self, Self::Full)
     }
 
-    const fn total(self) -> Balance {
-        Balance {
-            funded: self.mempool.funded + self.confirmed.funded,
-            spent: self.mempool.spent + self.confirmed.spent,
+    /// How far back to fetch, given the newest confirmed transaction we already know about
+    pub(crate) fn history_limit(self, last_txid: Option<Txid>, last_height: Option<u32>) -> HistoryLimit {
+        match self {
+            Self::Full => HistoryLimit {
+                until_txid: last_txid,
+                until_height: last_height,
+                max_txs: None,
+            },
+            Self::Recent(n) => HistoryLimit {
+                until_txid: last_txid,
+                until_height: last_height,
+                max_txs: Some(n),
+            },
+            Self::Since(height) => HistoryLimit {
+                until_txid: last_txid.filter(|_| last_height >= Some(height)),
+                until_height: Some(last_height.map_or(height, |last_height| last_height.max(height))),
+                max_txs: None,
+            },
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Progress of syncing an address history from the REST API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum SyncStatus {
+    /// waiting to (re)sync
+    Pending,
+    Syncing,
+    Ready,
+    /// the last sync attempt failed, and will be retried
+    Failed(String),
+    /// the address was unwatched, so this state will never be updated again
+    Unwatched,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct State {
     pub scriptpubkey: ScriptBuf,
-    pub transactions: Vec<Tx>,
-    pub balance: Balances,
+    /// handles into the wallet's shared transaction store, oldest first
+    #[serde(with = "schema::arc_txs")]
+    pub transactions: Vec<Arc<Tx>>,
+    pub balance: Balance,
+    pub sync_status: SyncStatus,
+    /// ancestors, descendants and effective feerate of unconfirmed transactions,
+    /// as of the last sync or mempool event, for those the backend could provide them for
+    #[serde(default)]
+    pub packages: HashMap<Txid, CpfpInfo>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Tracker {
     scriptpubkey: ScriptBuf,
-    transactions: HashMap<Txid, Tx>,
-    balance: Balances,
+    transactions: HashMap<Txid, Arc<Tx>>,
+    /// package information for unconfirmed transactions
+    packages: HashMap<Txid, CpfpInfo>,
+    store: TxStore,
+    /// confirmed totals, kept as running totals since a bounded window
+    /// does not hold every transaction
+    confirmed: TxoStats,
+    /// contributions to `confirmed` of transactions evicted from the window,
+    /// so that later events about them are neither ignored nor counted twice
+    evicted: HashMap<Txid, TxoStats>,
+    /// set when the tracked state has drifted from the server,
+    /// until a sync of the whole history window succeeds
+    full_resync: bool,
+    tip: ChainTip,
     queue: VecDeque<Event>,
-    loading: bool,
-    event_sender: broadcast::Sender<WalletEvent>,
+    sync_status: SyncStatus,
+    window: HistoryWindow,
+    history_cursor: HistoryCursor,
+    sync_cancel: CancellationToken,
+    sync_lock: Arc<Mutex<()>>,
+    journal: EventJournal,
+    /// watched until explicitly unwatched
+    permanent: bool,
+    /// number of live [`WatchGuard`](crate::wallet::WatchGuard)s watching this address
+    guards: usize,
 }
 
 // this is a dumb way to order transactions, but suffices for now
@@ -117,147 +213,628 @@ fn partial_cmp_tx_time(a: &Tx, b: &Tx) -> Option<Ordering> {
         a.txid.partial_cmp(&b.txid)
     }
 }
-fn cmp_tx_time(a: &Tx, b: &Tx) -> Ordering {
+pub(crate) fn cmp_tx_time(a: &Tx, b: &Tx) -> Ordering {
     partial_cmp_tx_time(a, b).expect("no duplicate transactions")
 }
 
 impl Tracker {
     #[must_use]
-    pub fn new(scriptpubkey: ScriptBuf, event_sender: broadcast::Sender<WalletEvent>) -> Self {
+    pub fn new(
+        scriptpubkey: ScriptBuf,
+        store: TxStore,
+        tip: ChainTip,
+        journal: EventJournal,
+    ) -> Self {
         Self {
             scriptpubkey,
             transactions: HashMap::new(),
-            balance: Balances::new(),
+            packages: HashMap::new(),
+            store,
+            confirmed: TxoStats::default(),
+            evicted: HashMap::new(),
+            full_resync: false,
+            tip,
             queue: VecDeque::new(),
-            loading: true,
-            event_sender,
+            sync_status: SyncStatus::Pending,
+            window: HistoryWindow::Full,
+            history_cursor: HistoryCursor::default(),
+            sync_cancel: CancellationToken::new(),
+            sync_lock: Arc::new(Mutex::new(())),
+            journal,
+            permanent: false,
+            guards: 0,
         }
     }
 
     #[must_use]
-    pub fn from(state: State, event_sender: broadcast::Sender<WalletEvent>) -> Self {
-        let mut tracker = Self::new(state.scriptpubkey, event_sender);
+    pub fn from(
+        state: State,
+        store: TxStore,
+        tip: ChainTip,
+        journal: EventJournal,
+    ) -> Self {
+        let mut tracker = Self::new(state.scriptpubkey, store, tip, journal);
 
         for tx in &state.transactions {
             tracker.add_transaction(tx);
         }
+        tracker.set_packages(state.packages);
 
         tracker
     }
 
+    /// Only fetch and keep the part of the history inside `window`
+    #[must_use]
+    pub const fn with_window(mut self, window: HistoryWindow) -> Self {
+        self.window = window;
+        self
+    }
+
+    #[must_use]
+    pub const fn window(&self) -> HistoryWindow {
+        self.window
+    }
+
+    #[must_use]
     pub fn get_state(&self) -> State {
         let mut transactions: Vec<_> = self.transactions.values().cloned().collect();
-        transactions.sort_by(cmp_tx_time);
+        transactions.sort_by(|a, b| cmp_tx_time(a, b));
         State {
             scriptpubkey: self.scriptpubkey.clone(),
             transactions,
-            balance: self.balance.clone(),
+            balance: self.balance(),
+            sync_status: self.sync_status.clone(),
+            packages: self.packages.clone(),
         }
     }
 
     pub fn process_event(&mut self, event: Event, realtime: bool) {
-        if realtime && self.loading {
-            log::trace!("queuing event to process later {}", self.scriptpubkey);
-            self.queue.push_back(event);
+        if self.is_unwatched() {
+            log::trace!("ignoring event for unwatched address {}", self.scriptpubkey);
             return;
         }
+        if realtime && self.is_loading() {
+            self.queue_event(event);
+            return;
+        }
+
+        if let Some(event) = self.apply_event(event) {
+            self.notify(WalletEvent::AddressEvent(event));
+        }
+    }
+
+    /// Reconcile this tracker with freshly fetched history.
+    ///
+    /// Only transactions which are new, have changed status, or have disappeared
+    /// produce events, which are marked as [`WalletEvent::AddressResync`].
+    ///
+    /// Transactions confirmed at or below `last_height` were not refetched,
+    /// so are assumed to be unchanged.
+    pub(crate) fn apply_history(&mut self, fetched: &[Tx], last_height: Option<u32>) {
+        let fetched_txids: HashSet<Txid> = fetched.iter().map(|tx| tx.txid).collect();
 
-        match event {
-            Event::Mempool(_, tx) => {
-                self.add_transaction(&tx);
-                let _ = self.event_sender.send(WalletEvent::AddressEvent(Event::Mempool(
-                    self.scriptpubkey.clone(),
-                    tx.clone(),
-                )));
+        let mut removed: Vec<Arc<Tx>> = self
+            .transactions
+            .values()
+            .filter(|tx| {
+                !tx.status.confirmed || last_height.is_none() || tx.status.block_height > last_height
+            })
+            .filter(|tx| !fetched_txids.contains(&tx.txid))
+            .cloned()
+            .collect();
+        removed.sort_by(|a, b| cmp_tx_time(a, b));
+
+        let events = removed
+            .into_iter()
+            .rev()
+            .map(|tx| Event::Removed(self.scriptpubkey.clone(), (*tx).clone()))
+            .chain(fetched.iter().map(|tx| {
+                if tx.status.confirmed {
+                    Event::Confirmed(self.scriptpubkey.clone(), tx.clone())
+                } else {
+                    Event::Mempool(self.scriptpubkey.clone(), tx.clone())
+                }
+            }))
+            .collect::<Vec<_>>();
+
+        log::trace!("reconciling {} transactions {}", events.len(), self.scriptpubkey);
+
+        for event in events {
+            if let Some(event) = self.apply_event(event) {
+                self.notify(WalletEvent::AddressResync(event));
             }
-            Event::Confirmed(_, tx) => {
-                self.add_transaction(&tx);
-                let _ = self.event_sender.send(WalletEvent::AddressEvent(Event::Confirmed(
-                    self.scriptpubkey.clone(),
-                    tx.clone(),
-                )));
+        }
+    }
+
+    /// Replace the running confirmed totals with those taken from the REST address summary,
+    /// for addresses whose transactions are not all held in memory
+    pub(crate) const fn set_confirmed(&mut self, confirmed: TxoStats) {
+        self.confirmed = confirmed;
+    }
+
+    /// Attach package information to unconfirmed transactions,
+    /// ignoring any for transactions which are not tracked or have since confirmed
+    pub(crate) fn set_packages(&mut self, packages: HashMap<Txid, CpfpInfo>) {
+        for (txid, package) in packages {
+            if self.transactions.get(&txid).is_some_and(|tx| !tx.status.confirmed) {
+                self.packages.insert(txid, package);
+            }
+        }
+    }
+
+    /// Unconfirmed transactions, whose package information may be refreshed
+    pub(crate) fn unconfirmed_txids(&self) -> Vec<Txid> {
+        self.transactions
+            .values()
+            .filter(|tx| !tx.status.confirmed)
+            .map(|tx| tx.txid)
+            .collect()
+    }
+
+    /// Totals as the REST address summary would report them, according to the tracked state
+    pub(crate) fn stats(&self) -> AddressStats {
+        let mut mempool_stats = TxoStats::default();
+        for tx in self.transactions.values().filter(|tx| !tx.status.confirmed) {
+            mempool_stats.add(&txo_stats(tx, &self.scriptpubkey));
+        }
+        AddressStats {
+            chain_stats: self.confirmed,
+            mempool_stats,
+        }
+    }
+
+    /// Whether the tracked state agrees with the REST address summary
+    ///
+    /// A bounded window may not hold every unconfirmed transaction of a busy address,
+    /// so only the confirmed totals of bounded addresses are compared.
+    pub(crate) fn agrees_with(&self, remote: &AddressStats) -> bool {
+        let local = self.stats();
+        local.chain_stats == remote.chain_stats
+            && (self.window.is_bounded() || local.mempool_stats == remote.mempool_stats)
+    }
+
+    /// Make the next sync refetch the whole history window,
+    /// rather than only what is newer than the transactions already known
+    pub(crate) fn request_full_resync(&mut self) {
+        self.full_resync = true;
+        self.history_cursor = HistoryCursor::default();
+        self.set_sync_status(SyncStatus::Pending);
+    }
+
+    #[must_use]
+    pub(crate) const fn needs_full_resync(&self) -> bool {
+        self.full_resync
+    }
+
+    pub(crate) const fn full_resync_done(&mut self) {
+        self.full_resync = false;
+    }
+
+    fn balance(&self) -> Balance {
+        let tip = self.tip.height();
+        let is_ours = |scriptpubkey: &ScriptBuf| scriptpubkey == &self.scriptpubkey;
+        let mut balance = Balance::default();
+        balance.add_pending(self.transactions.values().map(|tx| &**tx), is_ours);
+        for tx in self.transactions.values() {
+            if balance::is_immature(tx, tip) {
+                balance.immature += balance::flows(tx, is_ours).0;
+            }
+        }
+        let net = Amount::from_sat(self.confirmed.funded_txo_sum.saturating_sub(self.confirmed.spent_txo_sum));
+        balance.confirmed = net.checked_sub(balance.immature).unwrap_or(Amount::ZERO);
+        balance
+    }
+
+    /// Drop confirmed transactions which have fallen outside the history window,
+    /// without affecting the balance
+    ///
+    /// Unconfirmed transactions and immature coinbase transactions are kept,
+    /// since the balance is worked out from them rather than from running totals.
+    fn evict(&mut self) {
+        let tip = self.tip.height();
+        let evictable = |tx: &Tx| tx.status.confirmed && !balance::is_immature(tx, tip);
+        let evicted: Vec<Txid> = match self.window {
+            HistoryWindow::Full => return,
+            HistoryWindow::Recent(n) => {
+                if self.transactions.len() <= n {
+                    return;
+                }
+                let mut txs: Vec<&Arc<Tx>> = self.transactions.values().collect();
+                txs.sort_by(|a, b| cmp_tx_time(a, b));
+                txs.iter()
+                    .take(txs.len() - n)
+                    .filter(|tx| evictable(tx))
+                    .map(|tx| tx.txid)
+                    .collect()
+            }
+            HistoryWindow::Since(height) => self
+                .transactions
+                .values()
+                .filter(|tx| evictable(tx) && tx.status.block_height < Some(height))
+                .map(|tx| tx.txid)
+                .collect(),
+        };
+
+        if !evicted.is_empty() {
+            log::trace!("evicting {} transactions {}", evicted.len(), self.scriptpubkey);
+        }
+        for txid in evicted {
+            if let Some(tx) = self.transactions.remove(&txid) {
+                self.evicted.insert(txid, txo_stats(&tx, &self.scriptpubkey));
+            }
+            self.packages.remove(&txid);
+            self.store.release(&txid);
+        }
+    }
+
+    /// Applies an event to the tracked state,
+    /// returning it again only if it actually changed something
+    fn apply_event(&mut self, event: Event) -> Option<Event> {
+        match &event {
+            Event::Mempool(_, tx) | Event::Confirmed(_, tx) => {
+                if self.transactions.get(&tx.txid).is_some_and(|known| known.status == tx.status) {
+                    return None;
+                }
+                // already counted in the confirmed totals
+                if tx.status.confirmed && self.evicted.contains_key(&tx.txid) {
+                    return None;
+                }
+                self.add_transaction(tx);
             }
             Event::Removed(_, tx) => {
+                if !self.transactions.contains_key(&tx.txid) && !self.evicted.contains_key(&tx.txid) {
+                    return None;
+                }
                 self.remove_transaction(&tx.txid);
-                let _ = self.event_sender.send(WalletEvent::AddressEvent(Event::Confirmed(
-                    self.scriptpubkey.clone(),
-                    tx.clone(),
-                )));
             }
         }
+        self.evict();
+        Some(event)
+    }
+
+    /// Realtime events are queued until the address history is ready
+    #[must_use]
+    pub fn is_loading(&self) -> bool {
+        self.sync_status != SyncStatus::Ready
     }
 
     pub fn set_loading(&mut self, loading: bool) {
-        if self.loading && !loading {
+        self.set_sync_status(if loading { SyncStatus::Syncing } else { SyncStatus::Ready });
+    }
+
+    #[must_use]
+    pub const fn sync_status(&self) -> &SyncStatus {
+        &self.sync_status
+    }
+
+    /// Once unwatched, the status can no longer change
+    pub fn set_sync_status(&mut self, sync_status: SyncStatus) {
+        if self.is_unwatched() {
+            return;
+        }
+        let was_loading = self.is_loading();
+        self.sync_status = sync_status;
+        if was_loading && !self.is_loading() {
             log::trace!("draining the event queue {}", self.queue.len());
             self.drain_queue();
         }
-        self.loading = loading;
+    }
+
+    /// Token which is cancelled when the current history sync should stop
+    pub(crate) fn sync_token(&self) -> CancellationToken {
+        self.sync_cancel.clone()
+    }
+
+    /// Interrupt any in-progress history sync for this address
+    pub(crate) fn cancel_sync(&mut self) {
+        self.sync_cancel.cancel();
+        if !self.is_unwatched() {
+            self.sync_cancel = CancellationToken::new();
+        }
+    }
+
+    /// Held for the duration of a history sync, so syncs of the same address run one at a time
+    pub(crate) fn sync_lock(&self) -> Arc<Mutex<()>> {
+        self.sync_lock.clone()
+    }
+
+    /// Record another reason to keep watching this address,
+    /// either a [`WatchGuard`](crate::wallet::WatchGuard) or a permanent watch
+    pub(crate) const fn hold(&mut self, guarded: bool) {
+        if guarded {
+            self.guards += 1;
+        } else {
+            self.permanent = true;
+        }
+    }
+
+    /// Drop a reason to keep watching this address,
+    /// returning true if there are none left and it should be unwatched
+    pub(crate) const fn release(&mut self, guarded: bool) -> bool {
+        if guarded {
+            self.guards = self.guards.saturating_sub(1);
+        } else {
+            self.permanent = false;
+        }
+        !self.permanent && self.guards == 0
+    }
+
+    /// Stop tracking this address for good.
+    ///
+    /// Any in-progress sync is cancelled, queued events are dropped,
+    /// and no further events are emitted for this address.
+    pub(crate) fn detach(&mut self) {
+        self.sync_status = SyncStatus::Unwatched;
+        self.queue.clear();
+        self.cancel_sync();
+    }
+
+    #[must_use]
+    pub fn is_unwatched(&self) -> bool {
+        self.sync_status == SyncStatus::Unwatched
+    }
+
+    /// Take the saved progress of an interrupted history sync, if any
+    pub(crate) fn take_history_cursor(&mut self) -> HistoryCursor {
+        std::mem::take(&mut self.history_cursor)
+    }
+
+    /// Save the progress of an interrupted history sync so the next sync can resume it
+    pub(crate) fn set_history_cursor(&mut self, cursor: HistoryCursor) {
+        self.history_cursor = cursor;
     }
 
     fn add_transaction(&mut self, tx: &Tx) {
         log::trace!("add transaction {} {}", tx.status.confirmed, tx.txid);
         // we already have a copy of this transaction
         // undo the effects of that version before applying this one
-        if self.transactions.contains_key(&tx.txid) {
+        if self.transactions.contains_key(&tx.txid) || self.evicted.contains_key(&tx.txid) {
             self.remove_transaction(&tx.txid);
         }
 
-        for vin in &tx.vin {
-            if let Some(prevout) = vin.prevout.as_ref() {
-                if prevout.scriptpubkey == self.scriptpubkey {
-                    if tx.status.confirmed {
-                        self.balance.confirmed.spent += prevout.value;
-                    } else {
-                        self.balance.mempool.spent += prevout.value;
-                    }
-                }
-            }
-        }
-
-        for vout in &tx.vout {
-            if vout.scriptpubkey == self.scriptpubkey {
-                if tx.status.confirmed {
-                    self.balance.confirmed.funded += vout.value;
-                } else {
-                    self.balance.mempool.funded += vout.value;
-                }
+        if tx.status.confirmed {
+            self.confirmed.add(&txo_stats(tx, &self.scriptpubkey));
+            if let Some(height) = tx.status.block_height {
+                self.tip.update(height);
             }
         }
 
-        self.transactions.insert(tx.txid, tx.clone());
+        self.transactions.insert(tx.txid, self.store.intern(tx));
     }
 
     fn remove_transaction(&mut self, txid: &Txid) {
+        self.packages.remove(txid);
+        if let Some(stats) = self.evicted.remove(txid) {
+            log::trace!("remove evicted transaction {txid}");
+            self.confirmed.remove(&stats);
+        }
         if let Some(tx) = self.transactions.remove(txid) {
             log::trace!("remove transaction {} {}", tx.status.confirmed, txid);
-            for vin in &tx.vin {
-                if let Some(prevout) = vin.prevout.as_ref() {
-                    if prevout.scriptpubkey == self.scriptpubkey {
-                        if tx.status.confirmed {
-                            self.balance.confirmed.spent -= prevout.value;
-                        } else {
-                            self.balance.mempool.spent -= prevout.value;
-                        }
-                    }
-                }
+            if tx.status.confirmed {
+                self.confirmed.remove(&txo_stats(&tx, &self.scriptpubkey));
             }
 
-            for vout in &tx.vout {
-                if vout.scriptpubkey == self.scriptpubkey {
-                    if tx.status.confirmed {
-                        self.balance.confirmed.funded -= vout.value;
-                    } else {
-                        self.balance.mempool.funded -= vout.value;
-                    }
-                }
-            }
+            drop(tx);
+            self.store.release(txid);
+        }
+    }
+
+    /// Queue a realtime event until the address history is ready.
+    ///
+    /// A failed sync may take a long time to succeed, so rather than queuing without bound
+    /// the queue is dropped for a full resync, which covers every event since it started.
+    fn queue_event(&mut self, event: Event) {
+        let waiting = self.sync_status != SyncStatus::Syncing;
+        if waiting && self.full_resync {
+            log::trace!("dropping event, a full resync is pending {}", self.scriptpubkey);
+            return;
+        }
+        if waiting && self.queue.len() >= MAX_QUEUED_EVENTS {
+            log::warn!("too many events queued, dropping them for a full resync {}", self.scriptpubkey);
+            self.queue.clear();
+            self.full_resync = true;
+            self.history_cursor = HistoryCursor::default();
+            return;
         }
+        log::trace!("queuing event to process later {}", self.scriptpubkey);
+        self.queue.push_back(event);
     }
 
     fn drain_queue(&mut self) {
         while let Some(event) = self.queue.pop_front() {
-            self.process_event(event, false);
+            if let Some(event) = self.apply_event(event) {
+                self.notify(WalletEvent::AddressEvent(event));
+            }
+        }
+    }
+
+    /// Unwatched addresses never emit events, even if a sync was already underway
+    fn notify(&self, event: WalletEvent) {
+        if !self.is_unwatched() {
+            self.journal.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast;
+
+    use super::*;
+    use crate::test_utils::{funding, spk, txid};
+    use crate::wallet::SequencedEvent;
+
+    fn tracker() -> Tracker {
+        Tracker::new(spk(), TxStore::new(), ChainTip::new(), EventJournal::new(16))
+    }
+
+    /// The resync events sent so far, as their kind and txid
+    fn resync_events(events: &mut broadcast::Receiver<SequencedEvent>) -> Vec<(&'static str, Txid)> {
+        let mut received = Vec::new();
+        while let Ok(sequenced) = events.try_recv() {
+            if let WalletEvent::AddressResync(event) = sequenced.event {
+                let kind = match event {
+                    Event::Mempool(..) => "mempool",
+                    Event::Confirmed(..) => "confirmed",
+                    Event::Removed(..) => "removed",
+                };
+                received.push((kind, event.tx().txid));
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn resync_emits_only_deltas() {
+        let mut tracker = tracker();
+        tracker.set_sync_status(SyncStatus::Ready);
+        tracker.process_event(Event::Confirmed(spk(), funding(1, 1_000, Some(100))), true);
+        tracker.process_event(Event::Mempool(spk(), funding(2, 500, None)), true);
+        tracker.process_event(Event::Mempool(spk(), funding(3, 200, None)), true);
+        let mut events = tracker.journal.subscribe();
+
+        // 1 is unchanged, 2 confirmed, 3 was evicted from the mempool and 4 is new
+        let fetched = [funding(1, 1_000, Some(100)), funding(2, 500, Some(101)), funding(4, 300, None)];
+        tracker.apply_history(&fetched, None);
+        assert_eq!(resync_events(&mut events), [("removed", txid(3)), ("confirmed", txid(2)), ("mempool", txid(4))]);
+        let balance = tracker.get_state().balance;
+        assert_eq!(balance.confirmed.to_sat(), 1_500);
+        assert_eq!(balance.untrusted_pending.to_sat(), 300);
+
+        // nothing changed since
+        tracker.apply_history(&fetched, None);
+        assert!(resync_events(&mut events).is_empty());
+    }
+
+    #[test]
+    fn resync_across_a_reorg() {
+        let mut tracker = tracker();
+        tracker.set_sync_status(SyncStatus::Ready);
+        tracker.process_event(Event::Confirmed(spk(), funding(1, 1_000, Some(100))), true);
+        tracker.process_event(Event::Confirmed(spk(), funding(2, 500, Some(101))), true);
+        tracker.process_event(Event::Confirmed(spk(), funding(3, 200, Some(101))), true);
+        let mut events = tracker.journal.subscribe();
+
+        // block 101 was reorged out: 2 went back to the mempool, 3 disappeared,
+        // and 1 was confirmed at or below the resume height so was not refetched
+        tracker.apply_history(&[funding(2, 500, None)], Some(100));
+        assert_eq!(resync_events(&mut events), [("removed", txid(3)), ("mempool", txid(2))]);
+        let balance = tracker.get_state().balance;
+        assert_eq!(balance.confirmed.to_sat(), 1_000);
+        assert_eq!(balance.untrusted_pending.to_sat(), 500);
+
+        // 2 is confirmed again in the new block 101
+        tracker.apply_history(&[funding(2, 500, Some(101))], Some(100));
+        assert_eq!(resync_events(&mut events), [("confirmed", txid(2))]);
+        assert_eq!(tracker.get_state().balance.confirmed.to_sat(), 1_500);
+    }
+
+    #[test]
+    fn evicted_transactions_stay_counted_once() {
+        let mut tracker = tracker().with_window(HistoryWindow::Recent(1));
+        tracker.set_sync_status(SyncStatus::Ready);
+        let confirmed = |tracker: &Tracker| tracker.get_state().balance.confirmed.to_sat();
+
+        tracker.process_event(Event::Confirmed(spk(), funding(1, 1_000, Some(100))), true);
+        tracker.process_event(Event::Mempool(spk(), funding(2, 500, None)), true);
+        tracker.process_event(Event::Mempool(spk(), funding(3, 200, None)), true);
+        // the confirmed transaction fell out of the window, the unconfirmed ones are kept
+        assert_eq!(tracker.transactions.len(), 2);
+        assert_eq!(confirmed(&tracker), 1_000);
+        assert_eq!(tracker.get_state().balance.untrusted_pending.to_sat(), 700);
+
+        tracker.process_event(Event::Confirmed(spk(), funding(2, 500, Some(101))), true);
+        assert_eq!(tracker.transactions.len(), 1);
+        assert_eq!(confirmed(&tracker), 1_500);
+        assert_eq!(tracker.get_state().balance.untrusted_pending.to_sat(), 200);
+
+        // a repeated confirmation of an evicted transaction is not counted again
+        tracker.process_event(Event::Confirmed(spk(), funding(2, 500, Some(101))), true);
+        assert_eq!(confirmed(&tracker), 1_500);
+
+        // a reorg still takes an evicted transaction back out of the totals
+        tracker.process_event(Event::Removed(spk(), funding(1, 1_000, Some(100))), true);
+        assert_eq!(confirmed(&tracker), 500);
+        tracker.process_event(Event::Mempool(spk(), funding(2, 500, None)), true);
+        assert_eq!(confirmed(&tracker), 0);
+        assert_eq!(tracker.get_state().balance.untrusted_pending.to_sat(), 700);
+    }
+
+    #[test]
+    fn bounded_windows_only_compare_confirmed_totals() {
+        let mut remote = AddressStats::default();
+        remote.chain_stats.tx_count = 1;
+        remote.chain_stats.funded_txo_count = 1;
+        remote.chain_stats.funded_txo_sum = 1_000;
+        // an unconfirmed transaction outside the window
+        remote.mempool_stats.tx_count = 1;
+
+        let mut full = tracker();
+        full.set_sync_status(SyncStatus::Ready);
+        full.process_event(Event::Confirmed(spk(), funding(1, 1_000, Some(100))), true);
+        assert!(!full.agrees_with(&remote));
+
+        let mut bounded = tracker().with_window(HistoryWindow::Recent(1));
+        bounded.set_sync_status(SyncStatus::Ready);
+        bounded.process_event(Event::Confirmed(spk(), funding(1, 1_000, Some(100))), true);
+        assert!(bounded.agrees_with(&remote));
+        remote.chain_stats.funded_txo_sum = 2_000;
+        assert!(!bounded.agrees_with(&remote));
+    }
+
+    #[test]
+    fn failed_sync_drops_queue_for_full_resync() {
+        let mut tracker = tracker();
+        tracker.set_sync_status(SyncStatus::Failed("offline".to_string()));
+        let mut events = tracker.journal.subscribe();
+
+        for n in 0..=MAX_QUEUED_EVENTS {
+            let tx = funding(u8::try_from(n % 256).unwrap(), 1_000, None);
+            tracker.process_event(Event::Mempool(spk(), tx), true);
         }
+        assert!(tracker.queue.is_empty());
+        assert!(tracker.needs_full_resync());
+
+        // further events are covered by the pending resync
+        tracker.process_event(Event::Mempool(spk(), funding(1, 1_000, None)), true);
+        assert!(tracker.queue.is_empty());
+
+        tracker.set_sync_status(SyncStatus::Ready);
+        assert!(tracker.transactions.is_empty());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn syncing_address_queues_without_bound() {
+        let mut tracker = tracker();
+        tracker.set_sync_status(SyncStatus::Syncing);
+        for n in 0..=MAX_QUEUED_EVENTS {
+            let tx = funding(u8::try_from(n % 256).unwrap(), 1_000, None);
+            tracker.process_event(Event::Mempool(spk(), tx), true);
+        }
+        assert_eq!(tracker.queue.len(), MAX_QUEUED_EVENTS + 1);
+        assert!(!tracker.needs_full_resync());
+    }
+
+    #[test]
+    fn unwatched_only_once_nothing_holds_the_watch() {
+        let mut tracker = tracker();
+        tracker.hold(true);
+        tracker.hold(false);
+        // a guard dropped while the address is also watched permanently
+        assert!(!tracker.release(true));
+        assert!(tracker.release(false));
+
+        // overlapping guards
+        tracker.hold(true);
+        tracker.hold(true);
+        assert!(!tracker.release(true));
+        assert!(tracker.release(true));
+
+        // an explicit unwatch waits for the guards
+        tracker.hold(false);
+        tracker.hold(true);
+        assert!(!tracker.release(false));
+        assert!(tracker.release(true));
     }
 }
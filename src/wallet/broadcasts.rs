@@ -0,0 +1,323 @@
+use bitcoin::{BlockHash, Transaction, Txid};
+use esplora_client::TxStatus;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+
+use super::address;
+use super::internal::WatchEvent;
+use super::{Error, Wallet};
+use crate::api::broadcast::Submission;
+use crate::compat;
+
+/// Milliseconds between checks of a tracked transaction over the REST API,
+/// catching evictions the websocket does not report
+const BROADCAST_CHECK_INTERVAL: u64 = 60_000;
+
+/// Confirmations after which a broadcast transaction is no longer tracked
+const BROADCAST_FINAL_CONFIRMATIONS: u32 = 6;
+
+/// Rejection reasons which mean the backend already has the transaction
+const ALREADY_KNOWN: [&str; 3] = ["txn-already-in-mempool", "txn-already-known", "already in block chain"];
+
+/// Where a transaction broadcast with [`Wallet::broadcast_and_track`] is in its lifecycle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum BroadcastStatus {
+    /// accepted by the backend, but not seen in the mempool yet
+    Accepted,
+    /// refused by the backend, with the reason it gave
+    Rejected(String),
+    InMempool,
+    Confirmed { height: u32, block_hash: BlockHash },
+    /// dropped from the mempool, or from a reorged block, without a conflicting spend,
+    /// and being rebroadcast
+    ///
+    /// This may be followed by [`BroadcastStatus::Accepted`] before a handle sees it,
+    /// see [`BroadcastHandle::rebroadcasts`] to tell that it happened.
+    Evicted,
+    /// a conflicting transaction spent one of its inputs
+    Replaced(Txid),
+}
+
+impl BroadcastStatus {
+    /// Whether the transaction can no longer confirm
+    #[must_use]
+    pub const fn is_final(&self) -> bool {
+        matches!(self, Self::Rejected(_) | Self::Replaced(_))
+    }
+}
+
+/// Follows a transaction broadcast with [`Wallet::broadcast_and_track`]
+///
+/// Tracking stops once the transaction is rejected, replaced, or buried under
+/// enough confirmations, or once every clone of the handle has been dropped.
+#[derive(Debug, Clone)]
+pub struct BroadcastHandle {
+    txid: Txid,
+    status: watch::Receiver<Tracked>,
+}
+
+/// What a [`BroadcastHandle`] watches
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Tracked {
+    status: BroadcastStatus,
+    /// times the transaction was evicted and resubmitted,
+    /// which only ever grows so that no eviction goes unnoticed
+    rebroadcasts: u32,
+}
+
+impl BroadcastHandle {
+    #[must_use]
+    pub const fn txid(&self) -> Txid {
+        self.txid
+    }
+
+    #[must_use]
+    pub fn status(&self) -> BroadcastStatus {
+        self.status.borrow().status.clone()
+    }
+
+    /// Number of times the transaction has been evicted and successfully rebroadcast
+    #[must_use]
+    pub fn rebroadcasts(&self) -> u32 {
+        self.status.borrow().rebroadcasts
+    }
+
+    /// Wait for the status or the number of rebroadcasts to change,
+    /// returning the status, or `None` once tracking has stopped
+    pub async fn changed(&mut self) -> Option<BroadcastStatus> {
+        self.status.changed().await.ok()?;
+        Some(self.status.borrow_and_update().status.clone())
+    }
+}
+
+/// Update the status, only notifying the handle if it actually changed
+fn set_status(tracked: &watch::Sender<Tracked>, new: BroadcastStatus) {
+    tracked.send_if_modified(|current| {
+        if current.status == new {
+            return false;
+        }
+        log::trace!("broadcast status {:?} => {new:?}", current.status);
+        current.status = new;
+        true
+    });
+}
+
+/// Record a successful rebroadcast
+fn set_rebroadcast(tracked: &watch::Sender<Tracked>) {
+    tracked.send_modify(|current| {
+        log::trace!("broadcast status {:?} => {:?} (rebroadcast)", current.status, BroadcastStatus::Accepted);
+        current.status = BroadcastStatus::Accepted;
+        current.rebroadcasts += 1;
+    });
+}
+
+/// The status of a tracked transaction according to the REST API,
+/// or `None` if the backend no longer `knows` of it
+fn checked_status(tx_status: &TxStatus, known: bool) -> Option<BroadcastStatus> {
+    match (tx_status.block_height, tx_status.block_hash) {
+        (Some(height), Some(block_hash)) if tx_status.confirmed => Some(BroadcastStatus::Confirmed { height, block_hash }),
+        _ => known.then_some(BroadcastStatus::InMempool),
+    }
+}
+
+impl Wallet {
+    /// Broadcast a transaction, and keep track of it until it is buried under a few confirmations
+    ///
+    /// The transaction is followed over the websocket through one of its outputs' scriptpubkeys,
+    /// which is subscribed to for as long as the transaction is tracked without becoming
+    /// a wallet address, and checked periodically over
+    /// the REST API in case an eviction goes unreported. If it disappears from the mempool
+    /// without a conflicting spend of its inputs, it is rebroadcast.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction could not be submitted.
+    /// A rejection by the backend is not an error, but reported in the handle's status.
+    pub async fn broadcast_and_track(&self, tx: &Transaction) -> Result<BroadcastHandle, Error> {
+        let txid = tx.txid();
+        let events = self.watch_events();
+        let watch = match tx.output.iter().map(|output| &output.script_pubkey).find(|spk| !spk.is_op_return()) {
+            Some(scriptpubkey) => Some(self.watch_internal(scriptpubkey).await),
+            None => None,
+        };
+
+        let initial = match self.submit(tx).await? {
+            Submission::Accepted(_) => BroadcastStatus::Accepted,
+            Submission::Rejected(reason) => BroadcastStatus::Rejected(reason),
+        };
+        let (status, receiver) = watch::channel(Tracked {
+            status: initial,
+            rebroadcasts: 0,
+        });
+        if !status.borrow().status.is_final() {
+            let wallet = self.clone();
+            let tx = tx.clone();
+            self.spawn_task("broadcast tracking", async move {
+                wallet.track_broadcast(&tx, &status, events).await;
+                if let Some(watch) = watch {
+                    watch.release().await;
+                }
+            });
+        }
+        Ok(BroadcastHandle {
+            txid,
+            status: receiver,
+        })
+    }
+
+    /// Submit a transaction, treating one the backend already has as accepted
+    async fn submit(&self, tx: &Transaction) -> Result<Submission, Error> {
+        match self.api.broadcast_tx(tx).await? {
+            Submission::Rejected(reason) if ALREADY_KNOWN.iter().any(|known| reason.contains(known)) => {
+                Ok(Submission::Accepted(tx.txid()))
+            }
+            submission => Ok(submission),
+        }
+    }
+
+    async fn track_broadcast(
+        &self,
+        tx: &Transaction,
+        status: &watch::Sender<Tracked>,
+        mut events: broadcast::Receiver<WatchEvent>,
+    ) {
+        let txid = tx.txid();
+        // kept across iterations, so that events don't keep pushing the periodic check back
+        let mut next_check = Box::pin(compat::sleep(BROADCAST_CHECK_INTERVAL));
+        loop {
+            let current = status.borrow().status.clone();
+            if current.is_final() || self.is_buried(&current) {
+                break;
+            }
+            tokio::select! {
+                () = status.closed() => {
+                    log::trace!("broadcast handles of {txid} dropped");
+                    break;
+                }
+                event = events.recv() => match event {
+                    Ok(WatchEvent::Tx(event)) if event.tx().txid == txid => {
+                        match event {
+                            address::Event::Mempool(..) => set_status(status, BroadcastStatus::InMempool),
+                            address::Event::Confirmed(_, confirmed) => {
+                                if let (Some(height), Some(block_hash)) = (confirmed.status.block_height, confirmed.status.block_hash) {
+                                    set_status(status, BroadcastStatus::Confirmed { height, block_hash });
+                                }
+                            }
+                            address::Event::Removed(..) => self.handle_dropped(tx, status).await,
+                        }
+                    }
+                    Ok(WatchEvent::Tx(_)) => {}
+                    // events may have been missed
+                    Ok(WatchEvent::Reconnected) | Err(RecvError::Lagged(_)) => self.check_broadcast(tx, status).await,
+                    Err(RecvError::Closed) => break,
+                },
+                () = &mut next_check => {
+                    self.check_broadcast(tx, status).await;
+                    next_check.set(compat::sleep(BROADCAST_CHECK_INTERVAL));
+                }
+            }
+        }
+        log::trace!("stopped tracking broadcast {txid}");
+    }
+
+    fn is_buried(&self, status: &BroadcastStatus) -> bool {
+        let BroadcastStatus::Confirmed { height, .. } = status else {
+            return false;
+        };
+        self.tip_height().is_some_and(|tip| tip.saturating_sub(*height) + 1 >= BROADCAST_FINAL_CONFIRMATIONS)
+    }
+
+    /// Check on a tracked transaction over the REST API
+    async fn check_broadcast(&self, tx: &Transaction, status: &watch::Sender<Tracked>) {
+        let txid = tx.txid();
+        let tx_status = match self.api.get_tx_status(&txid).await {
+            Ok(tx_status) => tx_status,
+            Err(e) => {
+                log::warn!("failed to check on broadcast {txid}: {e:?}");
+                return;
+            }
+        };
+        // the status of an unknown transaction is unconfirmed rather than not found
+        let known = if tx_status.confirmed {
+            true
+        } else {
+            match self.api.get_tx(&txid).await {
+                Ok(found) => found.is_some(),
+                Err(e) => {
+                    log::warn!("failed to check on broadcast {txid}: {e:?}");
+                    return;
+                }
+            }
+        };
+        match checked_status(&tx_status, known) {
+            Some(checked) => set_status(status, checked),
+            None => self.handle_dropped(tx, status).await,
+        }
+    }
+
+    /// Rebroadcast a transaction which has disappeared, unless it has been replaced
+    async fn handle_dropped(&self, tx: &Transaction, status: &watch::Sender<Tracked>) {
+        let txid = tx.txid();
+        match self.find_conflict(tx).await {
+            Ok(Some(conflict)) => {
+                set_status(status, BroadcastStatus::Replaced(conflict));
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log::warn!("failed to check {txid} for conflicts: {e}");
+                return;
+            }
+        }
+        set_status(status, BroadcastStatus::Evicted);
+        log::debug!("rebroadcasting {txid}");
+        match self.submit(tx).await {
+            Ok(Submission::Accepted(_)) => set_rebroadcast(status),
+            Ok(Submission::Rejected(reason)) => {
+                // the conflict may only just have arrived
+                let rejected = match self.find_conflict(tx).await {
+                    Ok(Some(conflict)) => BroadcastStatus::Replaced(conflict),
+                    _ => BroadcastStatus::Rejected(reason),
+                };
+                set_status(status, rejected);
+            }
+            // retried at the next check
+            Err(e) => log::warn!("failed to rebroadcast {txid}: {e}"),
+        }
+    }
+
+    /// The transaction spending one of `tx`'s inputs in its place, if any
+    async fn find_conflict(&self, tx: &Transaction) -> Result<Option<Txid>, Error> {
+        let txid = tx.txid();
+        for input in &tx.input {
+            let prevout = input.previous_output;
+            let spend = self.api.get_output_status(&prevout.txid, u64::from(prevout.vout)).await?;
+            if let Some(spending_txid) = spend.filter(|spend| spend.spent).and_then(|spend| spend.txid) {
+                if spending_txid != txid {
+                    return Ok(Some(spending_txid));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+    use crate::test_utils::status;
+
+    #[test]
+    fn unknown_unconfirmed_transactions_are_gone() {
+        assert_eq!(checked_status(&status(None), true), Some(BroadcastStatus::InMempool));
+        assert_eq!(checked_status(&status(None), false), None);
+
+        let block_hash = BlockHash::from_byte_array([7; 32]);
+        let confirmed = TxStatus { block_hash: Some(block_hash), ..status(Some(100)) };
+        assert_eq!(checked_status(&confirmed, true), Some(BroadcastStatus::Confirmed { height: 100, block_hash }));
+    }
+}
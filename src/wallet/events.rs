@@ -0,0 +1,317 @@
+use std::collections::{HashSet, VecDeque};
+use std::future::ready;
+
+use bitcoin::{Amount, ScriptBuf};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use super::journal::{EventJournal, SavedJournal, SequencedEvent};
+use super::{Event, State, Wallet};
+
+/// The kind of a wallet [`Event`], without its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum EventKind {
+    Initializing,
+    Disconnected,
+    SyncProgress,
+    AddressReady,
+    AddressSyncFailed,
+    AddressEvent,
+    TaskFailed,
+    AddressResync,
+    Snapshot,
+    Inconsistency,
+    OutpointSpent,
+}
+
+impl Event {
+    #[must_use]
+    pub const fn kind(&self) -> EventKind {
+        match self {
+            Self::Initializing => EventKind::Initializing,
+            Self::Disconnected => EventKind::Disconnected,
+            Self::SyncProgress { .. } => EventKind::SyncProgress,
+            Self::AddressReady(_) => EventKind::AddressReady,
+            Self::AddressSyncFailed { .. } => EventKind::AddressSyncFailed,
+            Self::AddressEvent(_) => EventKind::AddressEvent,
+            Self::TaskFailed { .. } => EventKind::TaskFailed,
+            Self::AddressResync(_) => EventKind::AddressResync,
+            Self::Snapshot(_) => EventKind::Snapshot,
+            Self::Inconsistency { .. } => EventKind::Inconsistency,
+            Self::OutpointSpent { .. } => EventKind::OutpointSpent,
+        }
+    }
+
+    /// The address this event is about, if it is about a single address
+    #[must_use]
+    pub const fn scriptpubkey(&self) -> Option<&ScriptBuf> {
+        match self {
+            Self::SyncProgress { scriptpubkey, .. }
+            | Self::AddressReady(scriptpubkey)
+            | Self::AddressSyncFailed { scriptpubkey, .. }
+            | Self::Inconsistency { scriptpubkey, .. } => Some(scriptpubkey),
+            Self::AddressEvent(event) | Self::AddressResync(event) => Some(event.scriptpubkey()),
+            Self::Initializing
+            | Self::Disconnected
+            | Self::TaskFailed { .. }
+            | Self::Snapshot(_)
+            | Self::OutpointSpent { .. } => None,
+        }
+    }
+}
+
+/// Builder for a filtered stream of wallet events, created by [`Wallet::events`]
+///
+/// Each event comes with its sequence number. If the stream falls behind,
+/// it catches up from the wallet's journal, or with a fresh [`Event::Snapshot`]
+/// if the events it missed are no longer in the journal.
+///
+/// ```ignore
+/// let mut events = wallet
+///     .events_since(last_processed_seq)
+///     .scriptpubkeys(&[scriptpubkey])
+///     .confirmed_only()
+///     .subscribe()
+///     .await;
+/// while let Some(SequencedEvent { seq, event }) = events.next().await {
+///     // ...
+/// }
+/// ```
+#[derive(Clone)]
+#[must_use = "no events are received until `subscribe` is called"]
+pub struct Events {
+    wallet: Wallet,
+    filter: Filter,
+    snapshot: bool,
+    since: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Filter {
+    scriptpubkeys: Option<HashSet<ScriptBuf>>,
+    kinds: Option<HashSet<EventKind>>,
+    min_value: Amount,
+    confirmed_only: bool,
+}
+
+impl Filter {
+    /// Snapshots always pass, but only keep the states of matching addresses
+    fn apply(&self, mut sequenced: SequencedEvent) -> Option<SequencedEvent> {
+        if let Event::Snapshot(states) = &mut sequenced.event {
+            states.retain(|state| self.matches_scriptpubkey(&state.scriptpubkey));
+            return Some(sequenced);
+        }
+        self.matches(&sequenced.event).then_some(sequenced)
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        if self.kinds.as_ref().is_some_and(|kinds| !kinds.contains(&event.kind())) {
+            return false;
+        }
+        if event.scriptpubkey().is_some_and(|scriptpubkey| !self.matches_scriptpubkey(scriptpubkey)) {
+            return false;
+        }
+        match event {
+            Event::AddressEvent(event) | Event::AddressResync(event) => {
+                (!self.confirmed_only || event.tx().status.confirmed)
+                    && event.value().to_sat().unsigned_abs() >= self.min_value.to_sat()
+            }
+            _ => true,
+        }
+    }
+
+    fn matches_scriptpubkey(&self, scriptpubkey: &ScriptBuf) -> bool {
+        self.scriptpubkeys
+            .as_ref()
+            .is_none_or(|scriptpubkeys| scriptpubkeys.contains(scriptpubkey))
+    }
+}
+
+impl Events {
+    pub(crate) fn new(wallet: Wallet) -> Self {
+        Self {
+            wallet,
+            filter: Filter::default(),
+            snapshot: false,
+            since: None,
+        }
+    }
+
+    /// Only receive events about these addresses.
+    ///
+    /// Events which are not about any one address, like [`Event::Disconnected`],
+    /// are still received.
+    pub fn scriptpubkeys(mut self, scriptpubkeys: &[ScriptBuf]) -> Self {
+        self.filter.scriptpubkeys = Some(scriptpubkeys.iter().cloned().collect());
+        self
+    }
+
+    /// Only receive events of these kinds (snapshots are always received)
+    pub fn kinds(mut self, kinds: &[EventKind]) -> Self {
+        self.filter.kinds = Some(kinds.iter().copied().collect());
+        self
+    }
+
+    /// Only receive transaction events which move at least `min_value`
+    /// into or out of the address
+    pub const fn min_value(mut self, min_value: Amount) -> Self {
+        self.filter.min_value = min_value;
+        self
+    }
+
+    /// Only receive transaction events for confirmed transactions,
+    /// including confirmed transactions removed by a reorg
+    pub const fn confirmed_only(mut self) -> Self {
+        self.filter.confirmed_only = true;
+        self
+    }
+
+    /// Start the stream with an [`Event::Snapshot`] of the state of every matching address.
+    ///
+    /// The snapshot reflects exactly the events numbered up to its own sequence number,
+    /// so nothing is missed or seen twice. The snapshot is only filtered by scriptpubkey.
+    pub const fn with_snapshot(mut self) -> Self {
+        self.snapshot = true;
+        self
+    }
+
+    /// Start the stream after the event numbered `seq`, replaying the events since.
+    ///
+    /// If some of those events are no longer in the wallet's journal, or `seq` came
+    /// from an earlier run of the process whose journal was not restored through
+    /// [`Options::saved_journal`](super::Options::saved_journal), the stream starts with a snapshot instead.
+    pub const fn since(mut self, seq: u64) -> Self {
+        self.since = Some(seq);
+        self
+    }
+
+    /// Start receiving events
+    pub async fn subscribe(self) -> impl Stream<Item = SequencedEvent> + Send + Unpin {
+        let Self { wallet, filter, snapshot, since } = self;
+
+        let (receiver, backlog, last_seq) = if let Some(seq) = since {
+            if let Some((receiver, missed)) = wallet.journal.subscribe_since(seq) {
+                (receiver, missed, seq)
+            } else {
+                log::debug!("cannot resume events from {seq}, starting from a snapshot");
+                wallet.subscribe_with_snapshot().await
+            }
+        } else if snapshot {
+            wallet.subscribe_with_snapshot().await
+        } else {
+            (wallet.journal.subscribe(), Vec::new(), 0)
+        };
+
+        let cursor = Cursor {
+            wallet,
+            receiver,
+            backlog: backlog.into(),
+            last_seq,
+        };
+        let events = stream::unfold(cursor, |mut cursor| async move {
+            cursor.next().await.map(|event| (event, cursor))
+        });
+        Box::pin(events.filter_map(move |event| ready(filter.apply(event))))
+    }
+}
+
+/// Position of a subscriber in the wallet's sequence of events
+struct Cursor {
+    wallet: Wallet,
+    receiver: broadcast::Receiver<SequencedEvent>,
+    /// events to deliver before any more from the receiver
+    backlog: VecDeque<SequencedEvent>,
+    last_seq: u64,
+}
+
+impl Cursor {
+    async fn next(&mut self) -> Option<SequencedEvent> {
+        loop {
+            if let Some(event) = self.backlog.pop_front() {
+                self.last_seq = event.seq;
+                return Some(event);
+            }
+            match self.receiver.recv().await {
+                // already delivered from the backlog
+                Ok(event) if event.seq <= self.last_seq => {}
+                Ok(event) => {
+                    self.last_seq = event.seq;
+                    return Some(event);
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::debug!("event stream fell behind by {skipped} events, catching up");
+                    if let Some(missed) = self.wallet.journal.since(self.last_seq) {
+                        self.backlog.extend(missed);
+                    } else {
+                        log::warn!("missed events are no longer in the journal, taking a snapshot");
+                        self.backlog.push_back(self.wallet.snapshot().await);
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Wallet {
+    /// The journal of recent events, to be passed as [`Options::saved_journal`](super::Options::saved_journal) after a restart
+    ///
+    /// Save it along with the sequence number of the last event processed,
+    /// so that no event can be numbered beyond the saved journal.
+    #[must_use]
+    pub fn save_journal(&self) -> SavedJournal {
+        self.journal.save()
+    }
+
+    /// A filtered stream of wallet events
+    pub fn events(&self) -> Events {
+        Events::new(self.clone())
+    }
+
+    /// A filtered stream of wallet events, resuming after the event numbered `seq`
+    ///
+    /// See [`Events::since`]
+    pub fn events_since(&self, seq: u64) -> Events {
+        self.events().since(seq)
+    }
+
+    /// Subscribe to events, starting with a snapshot of every address
+    async fn subscribe_with_snapshot(
+        &self,
+    ) -> (broadcast::Receiver<SequencedEvent>, Vec<SequencedEvent>, u64) {
+        let (states, (receiver, head)) = self.with_consistent_state(EventJournal::subscribe_from_head).await;
+        let snapshot = SequencedEvent {
+            seq: head,
+            event: Event::Snapshot(states),
+        };
+        (receiver, vec![snapshot], head)
+    }
+
+    /// The state of every address, numbered with the last event it reflects
+    async fn snapshot(&self) -> SequencedEvent {
+        let (states, head) = self.with_consistent_state(EventJournal::head).await;
+        SequencedEvent {
+            seq: head,
+            event: Event::Snapshot(states),
+        }
+    }
+
+    /// The state of every address, along with the result of `f`
+    /// called on the journal at the moment the states were taken
+    async fn with_consistent_state<T>(&self, f: impl FnOnce(&EventJournal) -> T) -> (Vec<State>, T) {
+        let addresses = self.addresses.lock().await;
+        // events which change an address are only sent while its tracker is locked,
+        // so holding every tracker lock gives a consistent cut
+        let mut trackers = Vec::with_capacity(addresses.len());
+        for tracker_arc in addresses.values() {
+            trackers.push(tracker_arc.lock().await);
+        }
+        let result = f(&self.journal);
+        let states = trackers.iter().map(|tracker| tracker.get_state()).collect();
+        drop(trackers);
+        drop(addresses);
+        (states, result)
+    }
+}
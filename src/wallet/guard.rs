@@ -0,0 +1,67 @@
+use std::fmt;
+
+use bitcoin::ScriptBuf;
+
+use super::Wallet;
+
+/// Holds a watch of the scriptpubkeys it was created for, releasing it when dropped
+///
+/// A scriptpubkey is unwatched once no guard holds it any more, unless it is also
+/// watched permanently with [`Wallet::watch`], in which case it stays watched until
+/// [`Wallet::unwatch`]. Dropping the guard releases its watch in a background task,
+/// so must happen inside the runtime.
+#[must_use = "the scriptpubkeys are unwatched as soon as the guard is dropped"]
+pub struct WatchGuard {
+    wallet: Wallet,
+    scriptpubkeys: Vec<ScriptBuf>,
+}
+
+impl WatchGuard {
+    pub(crate) const fn new(wallet: Wallet, scriptpubkeys: Vec<ScriptBuf>) -> Self {
+        Self {
+            wallet,
+            scriptpubkeys,
+        }
+    }
+
+    /// The scriptpubkeys this guard holds a watch of
+    #[must_use]
+    pub fn scriptpubkeys(&self) -> &[ScriptBuf] {
+        &self.scriptpubkeys
+    }
+
+    /// Keep watching the scriptpubkeys permanently, as if by [`Wallet::watch`]
+    pub fn keep(mut self) {
+        let wallet = self.wallet.clone();
+        let scriptpubkeys = std::mem::take(&mut self.scriptpubkeys);
+        self.wallet.spawn_task("keep watch", async move {
+            wallet.keep_watches(&scriptpubkeys).await;
+        });
+    }
+
+    /// Release the watch now, rather than in the background once dropped
+    pub async fn release(mut self) {
+        let scriptpubkeys = std::mem::take(&mut self.scriptpubkeys);
+        self.wallet.release_watches(&scriptpubkeys, true).await;
+    }
+}
+
+impl fmt::Debug for WatchGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchGuard").field("scriptpubkeys", &self.scriptpubkeys).finish_non_exhaustive()
+    }
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        if self.scriptpubkeys.is_empty() {
+            return;
+        }
+        let wallet = self.wallet.clone();
+        let scriptpubkeys = std::mem::take(&mut self.scriptpubkeys);
+        log::trace!("watch guard dropped, releasing {} scriptpubkeys", scriptpubkeys.len());
+        self.wallet.spawn_task("unwatch", async move {
+            wallet.release_watches(&scriptpubkeys, true).await;
+        });
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use bitcoin::ScriptBuf;
+use tokio::sync::broadcast;
+
+use super::address;
+use super::Wallet;
+
+/// What the wallet's own watches of scriptpubkeys are told about
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    /// a transaction of a scriptpubkey watched by the wallet or for its own use
+    Tx(address::Event),
+    /// the websocket (re)connected, so transactions may have been missed while it was down
+    Reconnected,
+}
+
+/// Scriptpubkeys subscribed to for the wallet's own use, such as following
+/// a broadcast transaction or a watched outpoint
+///
+/// They are not wallet addresses: their transactions are only passed on as [`WatchEvent`]s,
+/// so they are left out of the wallet's state, summaries and events.
+#[derive(Debug, Clone)]
+pub struct InternalWatches {
+    /// number of live [`InternalWatch`]es of each scriptpubkey
+    counts: Arc<Mutex<HashMap<ScriptBuf, usize>>>,
+    events: broadcast::Sender<WatchEvent>,
+}
+
+impl InternalWatches {
+    pub fn new() -> Self {
+        Self {
+            counts: Arc::new(Mutex::new(HashMap::new())),
+            events: broadcast::channel(256).0,
+        }
+    }
+
+    fn hold(&self, scriptpubkey: &ScriptBuf) {
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        *counts.entry(scriptpubkey.clone()).or_default() += 1;
+    }
+
+    /// Drop a watch of `scriptpubkey`, returning true if that was the last one
+    fn release(&self, scriptpubkey: &ScriptBuf) -> bool {
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(count) = counts.get_mut(scriptpubkey) else {
+            return false;
+        };
+        *count -= 1;
+        if *count > 0 {
+            return false;
+        }
+        counts.remove(scriptpubkey);
+        true
+    }
+
+    pub fn contains(&self, scriptpubkey: &ScriptBuf) -> bool {
+        let counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        counts.contains_key(scriptpubkey)
+    }
+
+    pub fn scriptpubkeys(&self) -> Vec<ScriptBuf> {
+        let counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        counts.keys().cloned().collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<WatchEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn send(&self, event: WatchEvent) {
+        // nobody may be listening
+        let _ = self.events.send(event);
+    }
+}
+
+/// Keeps a scriptpubkey subscribed to for the wallet's own use, see [`InternalWatches`],
+/// until it is released or dropped
+///
+/// Like a [`WatchGuard`](super::WatchGuard), dropping it releases the watch in a background task.
+pub struct InternalWatch {
+    wallet: Wallet,
+    scriptpubkey: Option<ScriptBuf>,
+}
+
+impl InternalWatch {
+    /// Release the watch now, rather than in the background once dropped
+    pub async fn release(mut self) {
+        if let Some(scriptpubkey) = self.scriptpubkey.take() {
+            self.wallet.release_internal(&scriptpubkey).await;
+        }
+    }
+}
+
+impl fmt::Debug for InternalWatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InternalWatch").field("scriptpubkey", &self.scriptpubkey).finish_non_exhaustive()
+    }
+}
+
+impl Drop for InternalWatch {
+    fn drop(&mut self) {
+        let Some(scriptpubkey) = self.scriptpubkey.take() else {
+            return;
+        };
+        let wallet = self.wallet.clone();
+        self.wallet.spawn_task("internal unwatch", async move {
+            wallet.release_internal(&scriptpubkey).await;
+        });
+    }
+}
+
+impl Wallet {
+    /// Subscribe to `scriptpubkey` without making it a wallet address
+    ///
+    /// Its transactions are passed on through [`Wallet::watch_events`].
+    pub(crate) async fn watch_internal(&self, scriptpubkey: &ScriptBuf) -> InternalWatch {
+        // under the addresses lock, like watching an address
+        let addresses = self.addresses.lock().await;
+        self.internal.hold(scriptpubkey);
+        self.ws.track_scriptpubkeys(std::slice::from_ref(scriptpubkey));
+        drop(addresses);
+        InternalWatch {
+            wallet: self.clone(),
+            scriptpubkey: Some(scriptpubkey.clone()),
+        }
+    }
+
+    async fn release_internal(&self, scriptpubkey: &ScriptBuf) {
+        let addresses = self.addresses.lock().await;
+        if self.internal.release(scriptpubkey) && !addresses.contains_key(scriptpubkey) {
+            self.ws.untrack_scriptpubkeys(std::slice::from_ref(scriptpubkey));
+        }
+        drop(addresses);
+    }
+
+    /// Transactions of every watched scriptpubkey, whether a wallet address or watched
+    /// with [`Wallet::watch_internal`], as they arrive over the websocket
+    pub(crate) fn watch_events(&self) -> broadcast::Receiver<WatchEvent> {
+        self.internal.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{spk, theirs};
+
+    #[test]
+    fn released_once_every_watch_is() {
+        let internal = InternalWatches::new();
+        internal.hold(&spk());
+        internal.hold(&spk());
+        internal.hold(&theirs());
+        assert!(!internal.release(&spk()));
+        assert!(internal.contains(&spk()));
+        assert!(internal.release(&spk()));
+        assert!(!internal.contains(&spk()));
+        assert_eq!(internal.scriptpubkeys(), [theirs()]);
+
+        // releasing more often than held
+        assert!(!internal.release(&spk()));
+    }
+}
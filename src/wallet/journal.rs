@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use super::Event;
+use crate::compat;
+
+/// A wallet event, numbered in the order events were sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequencedEvent {
+    /// strictly increasing across every event sent by a wallet
+    pub seq: u64,
+    pub event: Event,
+}
+
+/// The contents of an [`EventJournal`], saved so that the next run of the process
+/// can replay the events in it and carry on numbering from where this one stopped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedJournal {
+    /// sequence number of the next event to be sent
+    pub next_seq: u64,
+    /// the most recent events, oldest first
+    pub events: Vec<SequencedEvent>,
+}
+
+#[derive(Debug)]
+struct Entries {
+    next_seq: u64,
+    capacity: usize,
+    events: VecDeque<SequencedEvent>,
+}
+
+/// Numbers wallet events, broadcasts them, and keeps the most recent ones
+/// so that subscribers can catch up on what they missed
+///
+/// A journal restored from a [`SavedJournal`] carries on numbering from where the saved one
+/// stopped, so subscribers can resume across restarts. For exactly-once delivery, save the
+/// journal together with the sequence number of the last event processed.
+///
+/// Otherwise, sequence numbers start from the time the journal was created, in microseconds,
+/// so that sequence numbers from an earlier run of the process are too old to be resumed from
+/// rather than being mistaken for recent ones, unless the clock has since been stepped back.
+#[derive(Debug, Clone)]
+pub struct EventJournal {
+    entries: Arc<Mutex<Entries>>,
+    sender: broadcast::Sender<SequencedEvent>,
+}
+
+impl EventJournal {
+    /// Keeps up to `capacity` of the most recent events
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        let next_seq = u64::try_from(compat::now().as_micros()).unwrap_or(u64::MAX / 2);
+        Self::with_entries(next_seq, capacity, VecDeque::with_capacity(capacity))
+    }
+
+    /// Carries on from a journal saved by an earlier run, keeping up to `capacity` of its events
+    #[must_use]
+    pub fn restore(saved: SavedJournal, capacity: usize) -> Self {
+        let mut events: VecDeque<SequencedEvent> = saved
+            .events
+            .into_iter()
+            .filter(|event| event.seq < saved.next_seq)
+            .collect();
+        events.make_contiguous().sort_by_key(|event| event.seq);
+        events.drain(..events.len().saturating_sub(capacity));
+        Self::with_entries(saved.next_seq.max(1), capacity, events)
+    }
+
+    fn with_entries(next_seq: u64, capacity: usize, events: VecDeque<SequencedEvent>) -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self {
+            entries: Arc::new(Mutex::new(Entries {
+                next_seq,
+                capacity,
+                events,
+            })),
+            sender,
+        }
+    }
+
+    /// The events in the journal and the next sequence number, for [`EventJournal::restore`]
+    #[must_use]
+    pub fn save(&self) -> SavedJournal {
+        let entries = self.lock();
+        SavedJournal {
+            next_seq: entries.next_seq,
+            events: entries.events.iter().cloned().collect(),
+        }
+    }
+
+    /// Number of events kept
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    /// Number, record and broadcast an event
+    #[allow(clippy::significant_drop_tightening)]
+    pub fn send(&self, event: Event) {
+        let mut entries = self.lock();
+        let sequenced = SequencedEvent {
+            seq: entries.next_seq,
+            event,
+        };
+        entries.next_seq += 1;
+        if entries.capacity > 0 {
+            if entries.events.len() >= entries.capacity {
+                entries.events.pop_front();
+            }
+            entries.events.push_back(sequenced.clone());
+        }
+        // broadcast under the lock, so subscribers see events in sequence order
+        let _ = self.sender.send(sequenced);
+    }
+
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Sequence number of the last event sent
+    #[must_use]
+    pub fn head(&self) -> u64 {
+        self.lock().next_seq - 1
+    }
+
+    /// Subscribe to new events, along with the sequence number of the last event sent
+    #[must_use]
+    pub fn subscribe_from_head(&self) -> (broadcast::Receiver<SequencedEvent>, u64) {
+        let entries = self.lock();
+        let receiver = self.sender.subscribe();
+        let head = entries.next_seq - 1;
+        drop(entries);
+        (receiver, head)
+    }
+
+    /// Subscribe to new events, along with every event sent after `seq`.
+    ///
+    /// Returns `None` if some of the events after `seq` are no longer in the journal,
+    /// or `seq` was never sent by this journal.
+    #[must_use]
+    pub fn subscribe_since(&self, seq: u64) -> Option<(broadcast::Receiver<SequencedEvent>, Vec<SequencedEvent>)> {
+        let entries = self.lock();
+        let missed = entries.since(seq)?;
+        let receiver = self.sender.subscribe();
+        drop(entries);
+        Some((receiver, missed))
+    }
+
+    /// Every event still in the journal which was sent after `seq`, if none are missing
+    #[must_use]
+    pub fn since(&self, seq: u64) -> Option<Vec<SequencedEvent>> {
+        self.lock().since(seq)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Entries {
+    fn since(&self, seq: u64) -> Option<Vec<SequencedEvent>> {
+        if seq >= self.next_seq {
+            return None;
+        }
+        let oldest = self.events.front().map_or(self.next_seq, |event| event.seq);
+        if seq + 1 < oldest {
+            return None;
+        }
+        Some(self.events.iter().filter(|event| event.seq > seq).cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(task: &str) -> Event {
+        Event::TaskFailed {
+            task: task.to_string(),
+            error: String::new(),
+        }
+    }
+
+    fn seqs(events: &[SequencedEvent]) -> Vec<u64> {
+        events.iter().map(|event| event.seq).collect()
+    }
+
+    #[test]
+    fn resumes_across_restart() {
+        let journal = EventJournal::new(3);
+        for task in ["a", "b", "c", "d", "e"] {
+            journal.send(failed(task));
+        }
+        let head = journal.head();
+        // the subscriber processed up to "c" before the process stopped
+        let processed = head - 2;
+
+        let saved = serde_json::to_string(&journal.save()).unwrap();
+        drop(journal);
+        let restored = EventJournal::restore(serde_json::from_str(&saved).unwrap(), 3);
+        assert_eq!(restored.head(), head);
+
+        let (mut receiver, missed) = restored.subscribe_since(processed).unwrap();
+        assert_eq!(seqs(&missed), [head - 1, head]);
+        assert!(matches!(&missed[0].event, Event::TaskFailed { task, .. } if task == "d"));
+
+        // numbering carries on without reusing any sequence number
+        restored.send(failed("f"));
+        assert_eq!(receiver.try_recv().unwrap().seq, head + 1);
+
+        // events which have fallen out of the journal can't be replayed
+        assert!(restored.since(head - 3).is_none());
+        assert_eq!(seqs(&restored.since(head - 2).unwrap()), [head - 1, head, head + 1]);
+    }
+
+    #[test]
+    fn restore_keeps_the_newest_events_that_fit() {
+        let journal = EventJournal::new(4);
+        for task in ["a", "b", "c", "d"] {
+            journal.send(failed(task));
+        }
+        let head = journal.head();
+        let restored = EventJournal::restore(journal.save(), 2);
+        assert_eq!(seqs(&restored.since(head - 2).unwrap()), [head - 1, head]);
+        assert!(restored.since(head - 3).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+use bitcoin::OutPoint;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::address;
+use super::internal::WatchEvent;
+use super::{CancellationToken, Error, Wallet};
+use crate::api::lightning::{ChannelClose, CloseKind};
+use crate::compat;
+
+/// Times to ask the explorer how a channel was closed, once the close is seen
+const CHANNEL_CLOSE_CLASSIFY_ATTEMPTS: usize = 6;
+
+impl Wallet {
+    /// Resolves once the channel funded by `funding` has been closed on chain
+    ///
+    /// The funding outpoint's scriptpubkey is subscribed to until it is spent,
+    /// without becoming a wallet address, so the wallet must be connected for the close
+    /// to be noticed. The explorer is then asked about the close every `poll_interval`
+    /// milliseconds until it knows of it. It may take a while longer to classify a close,
+    /// so while the kind of close is still [`CloseKind::Unknown`] it is looked up
+    /// a few more times before giving up and returning the close as it is.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInterval`] if `poll_interval` is zero,
+    /// [`Error::Cancelled`] once `cancel` is cancelled,
+    /// or [`Error::Missing`] if the funding outpoint does not exist
+    pub async fn wait_for_channel_close(
+        &self,
+        funding: &OutPoint,
+        poll_interval: u64,
+        cancel: &CancellationToken,
+    ) -> Result<ChannelClose, Error> {
+        if poll_interval == 0 {
+            return Err(Error::InvalidInterval);
+        }
+        tokio::select! {
+            biased;
+            () = cancel.cancelled() => Err(Error::Cancelled),
+            result = self.await_channel_close(funding, poll_interval) => result,
+        }
+    }
+
+    async fn await_channel_close(&self, funding: &OutPoint, poll_interval: u64) -> Result<ChannelClose, Error> {
+        let tx = self.api.get_tx(&funding.txid).await?.ok_or(Error::Missing)?;
+        let output = usize::try_from(funding.vout).ok().and_then(|vout| tx.output.get(vout));
+        let scriptpubkey = output.ok_or(Error::Missing)?.script_pubkey.clone();
+
+        let mut events = self.watch_events();
+        let watch = self.watch_internal(&scriptpubkey).await;
+        let mut spent = self.is_spent(funding).await?;
+        while !spent {
+            spent = match events.recv().await {
+                Ok(WatchEvent::Tx(address::Event::Mempool(_, tx) | address::Event::Confirmed(_, tx))) => {
+                    tx.vin.iter().any(|vin| vin.txid == funding.txid && vin.vout == funding.vout)
+                }
+                Ok(WatchEvent::Tx(_)) => false,
+                // transactions may have been missed
+                Ok(WatchEvent::Reconnected) | Err(RecvError::Lagged(_)) => self.is_spent(funding).await?,
+                Err(RecvError::Closed) => return Err(Error::Cancelled),
+            };
+        }
+        watch.release().await;
+
+        let mut close = loop {
+            if let Some(close) = self.api.get_channel_close(funding).await? {
+                break close;
+            }
+            compat::sleep(poll_interval).await;
+        };
+        let mut attempts = 1;
+        while close.kind == CloseKind::Unknown && attempts < CHANNEL_CLOSE_CLASSIFY_ATTEMPTS {
+            compat::sleep(poll_interval).await;
+            if let Some(classified) = self.api.get_channel_close(funding).await? {
+                close = classified;
+            }
+            attempts += 1;
+        }
+        Ok(close)
+    }
+
+    async fn is_spent(&self, outpoint: &OutPoint) -> Result<bool, Error> {
+        let status = self.api.get_output_status(&outpoint.txid, u64::from(outpoint.vout)).await?;
+        Ok(status.is_some_and(|status| status.spent))
+    }
+}
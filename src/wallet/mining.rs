@@ -0,0 +1,60 @@
+use bitcoin::{BlockHash, Txid};
+use esplora_client::Tx;
+use serde::{Deserialize, Serialize};
+
+use super::{Error, Wallet};
+use crate::api::mining::BlockPool;
+
+/// Who mined a transaction, and whether the blocks before it should have
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxMiningReport {
+    pub txid: Txid,
+    pub block_hash: BlockHash,
+    pub height: u32,
+    /// the pool which mined the block, if the backend could identify it
+    pub pool: Option<BlockPool>,
+    /// whether the backend expected the transaction in the block which mined it,
+    /// if that block was audited
+    pub expected: Option<bool>,
+    /// earlier blocks whose expected templates included the transaction, but which left it out
+    pub missing_from: Vec<BlockHash>,
+}
+
+impl Wallet {
+    /// Report which pool mined a confirmed transaction, and whether any of the
+    /// `lookback` blocks before it were expected to include it but didn't
+    ///
+    /// Returns `None` if the transaction is unconfirmed.
+    /// Blocks the backend did not audit are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if fetching the block, a block hash or an audit summary fails
+    pub async fn tx_mining_report(&self, tx: &Tx, lookback: u32) -> Result<Option<TxMiningReport>, Error> {
+        let (Some(block_hash), Some(height)) = (tx.status.block_hash, tx.status.block_height) else {
+            return Ok(None);
+        };
+
+        let block = self.api.get_block_extended(&block_hash).await?;
+        let expected = self.api.get_block_audit_summary(&block_hash).await?.map(|audit| {
+            !audit.is_added(&tx.txid) && !audit.fresh_txs.contains(&tx.txid)
+        });
+
+        let mut missing_from = Vec::new();
+        for earlier in (height.saturating_sub(lookback)..height).rev() {
+            let earlier_hash = self.api.get_block_hash(earlier).await?;
+            if self.api.get_block_audit_summary(&earlier_hash).await?.is_some_and(|audit| audit.is_missing(&tx.txid)) {
+                missing_from.push(earlier_hash);
+            }
+        }
+
+        Ok(Some(TxMiningReport {
+            txid: tx.txid,
+            block_hash,
+            height,
+            pool: block.extras.pool,
+            expected,
+            missing_from,
+        }))
+    }
+}
@@ -1,26 +1,110 @@
-use crate::api;
+use crate::api::mempool::CpfpInfo;
+use crate::api::{self, AddressStats, TxoStats};
 use crate::socket::{self, WebsocketEvent};
 use crate::compat;
-use bitcoin::ScriptBuf;
+use bitcoin::{OutPoint, ScriptBuf, Txid};
+use esplora_client::{Tx, TxStatus};
 pub use esplora_client;
-use tokio::sync::{broadcast, Mutex};
+pub use crate::api::RateLimit;
+pub use crate::cancel::CancellationToken;
+use futures_util::future::join_all;
+use futures_util::{Future, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch, Mutex, Semaphore, SemaphorePermit};
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 
 pub mod address;
-use address::{State, Tracker};
+pub mod broadcasts;
+mod events;
+mod guard;
+mod internal;
+pub mod journal;
+mod lightning;
+pub mod mining;
+pub mod outpoints;
+pub mod prices;
+pub mod schema;
+pub mod store;
+pub mod summary;
+pub mod tip;
+use address::{HistoryWindow, State, SyncStatus, Tracker};
+use store::TxStore;
+use tip::ChainTip;
+pub use events::{EventKind, Events};
+pub use guard::WatchGuard;
+use internal::{InternalWatches, WatchEvent};
+use journal::EventJournal;
+use outpoints::OutpointWatcher;
+use prices::PriceFeed;
+pub use journal::{SavedJournal, SequencedEvent};
+use summary::Summary;
 
+/// Delay in milliseconds before retrying a failed address sync, doubled for each further attempt
+const SYNC_RETRY_INITIAL_DELAY: u64 = 1_000;
+/// Upper bound in milliseconds on the delay between address sync attempts
+const SYNC_RETRY_MAX_DELAY: u64 = 300_000;
+
+/// Times to refetch a bounded history if the address summary changes while fetching it
+const BOUNDED_SYNC_ATTEMPTS: usize = 3;
+
+/// Maximum number of package information requests in flight for one address
+const MAX_CONCURRENT_PACKAGE_FETCHES: usize = 4;
+
+/// Default maximum number of address histories synced in parallel
+pub const DEFAULT_MAX_CONCURRENT_SYNCS: usize = 8;
+
+/// Default number of recent events kept for subscribers to catch up on
+pub const DEFAULT_EVENT_JOURNAL_CAPACITY: usize = 1_000;
+
+/// Default interval in milliseconds between checks of every address against the REST address summary
+pub const DEFAULT_RECONCILE_INTERVAL: u64 = 600_000;
+
+/// Delay in milliseconds before checking a mismatched address again,
+/// giving the websocket time to deliver anything in flight
+const RECONCILE_RECHECK_DELAY: u64 = 5_000;
+
+/// Interval in milliseconds between refreshes of the chain tip while connected,
+/// since the websocket only reports blocks containing watched transactions
+const TIP_REFRESH_INTERVAL: u64 = 60_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Options {
     pub hostname: String,
     pub secure: bool,
+    /// Maximum number of address histories to sync in parallel.
+    ///
+    /// Each sync pages through the REST API sequentially, so this is also
+    /// the maximum number of in-flight history requests.
+    pub max_concurrent_syncs: usize,
+    /// Rate limiting and retry policy shared by every REST request
+    pub rate_limit: RateLimit,
+    /// Number of recent events kept, so that subscribers can resume
+    /// from a sequence number without falling back to a snapshot
+    pub event_journal_capacity: usize,
+    /// Journal saved by an earlier run with [`Wallet::save_journal`], to carry on from,
+    /// so that [`Wallet::events_since`] can resume from sequence numbers handed out before a restart
+    #[serde(default)]
+    pub saved_journal: Option<SavedJournal>,
+    /// Milliseconds between checks of every address against the REST address summary,
+    /// in addition to the check after every (re)connect. Zero disables periodic checks.
+    pub reconcile_interval: u64,
+    /// Keep the latest exchange rates up to date while connected, see [`Wallet::prices`]
+    pub track_prices: bool,
 }
 
 #[derive(Debug)]
 pub enum Error {
     EsploraError(esplora_client::Error),
     Missing,
+    Cancelled,
+    Timeout,
+    /// the [`RateLimit`] in the [`Options`] can't be enforced
+    InvalidRateLimit(String),
+    /// a polling interval of zero was given
+    InvalidInterval,
 }
 
 impl fmt::Display for Error {
@@ -45,12 +129,65 @@ macro_rules! impl_error {
 impl std::error::Error for Error {}
 impl_error!(esplora_client::Error, EsploraError, Error);
 
-#[derive(Debug, Clone)]
+impl From<api::Error> for Error {
+    fn from(err: api::Error) -> Self {
+        match err {
+            api::Error::EsploraError(e) => Self::EsploraError(e),
+            api::Error::Cancelled => Self::Cancelled,
+            api::Error::InvalidRateLimit(reason) => Self::InvalidRateLimit(reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum Event {
+    /// The wallet has (re)connected and started syncing its addresses
     Initializing,
     Disconnected,
+    /// Another page of an address history has been fetched
+    SyncProgress {
+        scriptpubkey: ScriptBuf,
+        pages_fetched: usize,
+        txs_fetched: usize,
+        oldest_height_reached: Option<u32>,
+    },
     AddressReady(ScriptBuf),
+    /// Syncing an address history failed, and will be retried in the background
+    AddressSyncFailed {
+        scriptpubkey: ScriptBuf,
+        error: String,
+        attempts: u32,
+    },
     AddressEvent(address::Event),
+    /// A background task panicked, and has stopped
+    TaskFailed {
+        task: String,
+        error: String,
+    },
+    /// A change discovered by (re)syncing an address history over the REST API,
+    /// rather than received as a realtime update
+    AddressResync(address::Event),
+    /// The state of every address, sent at the start of an event stream
+    /// or when a stream has missed events, see [`Events::with_snapshot`]
+    Snapshot(Vec<State>),
+    /// The tracked state of an address disagreed with the REST address summary,
+    /// so its history window is being resynced
+    Inconsistency {
+        scriptpubkey: ScriptBuf,
+        /// totals according to the tracked state
+        local: AddressStats,
+        /// totals according to the server
+        remote: AddressStats,
+    },
+    /// A watched outpoint has been spent, reported once when the spend is seen
+    /// and again when it confirms, see [`Wallet::watch_outpoint`]
+    OutpointSpent {
+        outpoint: OutPoint,
+        spending_txid: Txid,
+        #[serde(with = "schema::status")]
+        status: TxStatus,
+    },
 }
 
 impl std::fmt::Display for Event {
@@ -62,24 +199,82 @@ impl std::fmt::Display for Event {
             Self::Disconnected => {
                 write!(f, "Lost connection")
             }
+            Self::SyncProgress { scriptpubkey, pages_fetched, txs_fetched, oldest_height_reached } => {
+                write!(f, "Syncing address {scriptpubkey} | {pages_fetched} pages | {txs_fetched} txs")?;
+                oldest_height_reached.map_or(Ok(()), |height| write!(f, " | back to block {height}"))
+            }
             Self::AddressReady(scriptpubkey) => {
                 write!(f, "Address ready {scriptpubkey}")
             }
+            Self::AddressSyncFailed { scriptpubkey, error, attempts } => {
+                write!(f, "Address sync failed {scriptpubkey} (attempt {attempts}): {error}")
+            }
             Self::AddressEvent(event) => event.fmt(f),
+            Self::TaskFailed { task, error } => {
+                write!(f, "Background task {task} failed: {error}")
+            }
+            Self::AddressResync(event) => write!(f, "resync | {event}"),
+            Self::Snapshot(states) => write!(f, "Snapshot of {} addresses", states.len()),
+            Self::Inconsistency { scriptpubkey, .. } => {
+                write!(f, "Address out of sync with the server, resyncing {scriptpubkey}")
+            }
+            Self::OutpointSpent { outpoint, spending_txid, status } => {
+                let state = if status.confirmed { "confirmed" } else { "mempool" };
+                write!(f, "Outpoint spent {outpoint} by {spending_txid} | {state}")
+            }
         }
     }
 }
 
+/// Where the wallet is in its connect/disconnect lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Lifecycle {
+    Disconnected,
+    /// waiting for the websocket to connect for the first time
+    Connecting,
+    /// connected, or reconnecting in the background after losing the connection
+    Connected,
+    /// cancelling syncs and closing the websocket
+    Disconnecting,
+}
+
+/// Wallet-wide sync progress
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    pub addresses_ready: usize,
+    pub addresses_total: usize,
+}
+
+impl Progress {
+    #[must_use]
+    pub const fn is_complete(&self) -> bool {
+        self.addresses_ready == self.addresses_total
+    }
+}
+
 #[derive(Clone)]
 pub struct Wallet {
     pub api: api::Client,
     ws: socket::Client,
     addresses: Arc<Mutex<HashMap<ScriptBuf, Arc<Mutex<Tracker>>>>>,
-    event_sender: broadcast::Sender<Event>,
+    journal: EventJournal,
+    sync_permits: Arc<Semaphore>,
+    store: TxStore,
+    tip: ChainTip,
+    lifecycle: Arc<watch::Sender<Lifecycle>>,
+    reconcile_interval: u64,
+    prices: PriceFeed,
+    track_prices: bool,
+    outpoints: OutpointWatcher,
+    internal: InternalWatches,
 }
 
 impl Wallet {
-    pub fn new(options: &Options) -> Result<Self, esplora_client::Error> {
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidRateLimit`] if `options.rate_limit` is invalid
+    pub fn new(options: &Options) -> Result<Self, Error> {
         let api_url = format!(
             "http{}://{}/api",
             if options.secure { "s" } else { "" },
@@ -91,72 +286,253 @@ impl Wallet {
             options.hostname
         );
 
-        let (event_sender, _) = broadcast::channel::<Event>(256);
-
-        api::Client::new(&api_url).map(|api| {
-            Self {
-                api,
-                ws: socket::Client::new(ws_url),
-                addresses: Arc::new(Mutex::new(HashMap::new())),
-                event_sender,
-            }
+        Ok(Self {
+            api: api::Client::new(&api_url, options.rate_limit)?,
+            ws: socket::Client::new(ws_url),
+            addresses: Arc::new(Mutex::new(HashMap::new())),
+            journal: options.saved_journal.clone().map_or_else(
+                || EventJournal::new(options.event_journal_capacity),
+                |saved| EventJournal::restore(saved, options.event_journal_capacity),
+            ),
+            sync_permits: Arc::new(Semaphore::new(options.max_concurrent_syncs.max(1))),
+            store: TxStore::new(),
+            tip: ChainTip::new(),
+            lifecycle: Arc::new(watch::channel(Lifecycle::Disconnected).0),
+            reconcile_interval: options.reconcile_interval,
+            prices: PriceFeed::new(),
+            track_prices: options.track_prices,
+            outpoints: OutpointWatcher::new(),
+            internal: InternalWatches::new(),
         })
-
-        
     }
 
+    /// Connect to the websocket, and sync watched addresses whenever it (re)connects
+    ///
+    /// Does nothing if the wallet is already connected or connecting.
+    /// If the wallet is still disconnecting, waits for that to finish first.
     pub async fn connect(&self, wait_for_connection: bool) {
         log::trace!("connecting wallet");
-        let wallet = self.clone();
-        log::trace!("wallet spawning event handling thread");
-        compat::spawn(async move {
-            let mut ws_rx = wallet.ws.subscribe();
-            log::trace!("wallet spawned event handling thread");
-            loop {
-                log::trace!("...wallet event receive loop...");
-                match ws_rx.recv().await {
-                    Ok(WebsocketEvent::Offline) => {
-                        log::trace!("wallet websocket offline!");
-                        break;
-                    }
-                    Ok(WebsocketEvent::Disconnected) => {
-                        log::trace!("wallet websocket disconnected!");
-                        let _ = wallet.event_sender.send(Event::Disconnected);
-                    }
-                    Ok(WebsocketEvent::Connected) => {
-                        log::trace!("wallet websocket (re)connected!");
-                        wallet.init_addresses().await;
-                        log::trace!("wallet initialized addresses");
-                    }
-                    Ok(WebsocketEvent::Error) => {
-                        log::trace!("wallet websocket threw an error");
-                    }
-                    Ok(WebsocketEvent::AddressEvent(address_event)) => {
-                        log::trace!("handling wallet ws event");
-                        wallet.handle_address_event(address_event, true).await;
-                        log::trace!("handled wallet ws event");
+        let mut lifecycle = self.lifecycle.subscribe();
+        loop {
+            let state = *lifecycle.borrow_and_update();
+            match state {
+                Lifecycle::Connecting | Lifecycle::Connected => {
+                    log::trace!("wallet is already {state:?}");
+                    return;
+                }
+                Lifecycle::Disconnecting => {
+                    log::trace!("waiting for wallet to finish disconnecting");
+                    if lifecycle.changed().await.is_err() {
+                        return;
                     }
-                    Err(e) => {
-                        log::warn!("unexpected websocket error {:?}", e);
+                }
+                Lifecycle::Disconnected => {
+                    if self.transition(&[Lifecycle::Disconnected], Lifecycle::Connecting) {
+                        break;
                     }
                 }
             }
-            log::trace!("wallet event loop ended");
-        });
+        }
+
+        log::trace!("wallet spawning event handling thread");
+        let ws_rx = self.ws.subscribe();
+        // stops the periodic checks and address inits started by the event handler
+        let stop = CancellationToken::new();
+        let wallet = self.clone();
+        let handler_stop = stop.clone();
+        let on_panic = {
+            let wallet = self.clone();
+            move || {
+                // without its event handler the wallet can't stay connected
+                stop.cancel();
+                let disconnecting = wallet.clone();
+                wallet.spawn_task("disconnect", async move {
+                    disconnecting.disconnect(false).await;
+                });
+            }
+        };
+        self.spawn_task_or("event handler", async move {
+            wallet.handle_events(ws_rx, &handler_stop).await;
+        }, on_panic);
         log::trace!("wallet waiting for connection");
         self.ws.start(wait_for_connection).await;
+        // unless disconnect was called in the meantime
+        self.transition(&[Lifecycle::Connecting], Lifecycle::Connected);
         log::trace!("wallet connected");
     }
 
+    /// Cancel in-progress syncs and close the websocket
+    ///
+    /// Does nothing if the wallet is already disconnected.
+    /// If `wait_for_close` is true, resolves once every background task has stopped.
     pub async fn disconnect(&self, wait_for_close: bool) {
         log::trace!("disconnecting wallet");
-        self.ws.stop(wait_for_close).await;
-        log::trace!("wallet disconnected");
+        let mut lifecycle = self.lifecycle.subscribe();
+        if self.transition(&[Lifecycle::Connecting, Lifecycle::Connected], Lifecycle::Disconnecting) {
+            let wallet = self.clone();
+            self.spawn_task("disconnect", async move {
+                wallet.cancel_syncs().await;
+                wallet.ws.stop(true).await;
+                wallet.lifecycle.send_replace(Lifecycle::Disconnected);
+                log::trace!("wallet disconnected");
+            });
+        }
+        if wait_for_close {
+            while *lifecycle.borrow_and_update() != Lifecycle::Disconnected {
+                if lifecycle.changed().await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Disconnect, waiting up to `timeout` milliseconds for every background task to stop
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Timeout`] if the wallet has not finished disconnecting in time,
+    /// in which case it carries on disconnecting in the background.
+    pub async fn disconnect_timeout(&self, timeout: u64) -> Result<(), Error> {
+        tokio::select! {
+            () = self.disconnect(true) => Ok(()),
+            () = compat::sleep(timeout) => Err(Error::Timeout),
+        }
+    }
+
+    #[must_use]
+    pub fn lifecycle(&self) -> Lifecycle {
+        *self.lifecycle.borrow()
+    }
+
+    /// Move to the `to` lifecycle state if currently in one of the `from` states
+    fn transition(&self, from: &[Lifecycle], to: Lifecycle) -> bool {
+        self.lifecycle.send_if_modified(|state| {
+            if from.contains(state) {
+                log::trace!("wallet {state:?} => {to:?}");
+                *state = to;
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Spawn a background task, reporting it as an [`Event::TaskFailed`] if it panics
+    fn spawn_task(&self, task: &'static str, future: impl Future<Output = ()> + Send + 'static) {
+        self.spawn_task_or(task, future, || {});
+    }
+
+    /// Like [`Wallet::spawn_task`], also calling `on_panic` to clean up if the task panics
+    fn spawn_task_or(
+        &self,
+        task: &'static str,
+        future: impl Future<Output = ()> + Send + 'static,
+        on_panic: impl FnOnce() + Send + 'static,
+    ) {
+        let journal = self.journal.clone();
+        compat::spawn_supervised(future, move |error| {
+            log::error!("wallet task {task} panicked: {error}");
+            journal.send(Event::TaskFailed {
+                task: task.to_string(),
+                error,
+            });
+            on_panic();
+        });
+    }
+
+    /// Handle websocket events until it goes offline
+    ///
+    /// `stop` is cancelled when this returns, or if it panics, stopping the
+    /// periodic checks and address inits it started.
+    async fn handle_events(&self, mut ws_rx: broadcast::Receiver<WebsocketEvent>, stop: &CancellationToken) {
+        log::trace!("wallet spawned event handling thread");
+        // periodic checks run for as long as this event loop does
+        if self.reconcile_interval > 0 {
+            let wallet = self.clone();
+            let stop = stop.clone();
+            self.spawn_task("reconcile", async move {
+                wallet.reconcile_periodically(&stop).await;
+            });
+        }
+        let wallet = self.clone();
+        let stop_refreshing = stop.clone();
+        self.spawn_task("tip refresh", async move {
+            wallet.refresh_tip_periodically(&stop_refreshing).await;
+        });
+        // cancelled when the last init finished,
+        // or when the connection came back again before it did
+        let mut current_init: Option<CancellationToken> = None;
+        loop {
+            log::trace!("...wallet event receive loop...");
+            match ws_rx.recv().await {
+                Ok(WebsocketEvent::Offline) => {
+                    log::trace!("wallet websocket offline!");
+                    break;
+                }
+                Ok(WebsocketEvent::Disconnected) => {
+                    log::trace!("wallet websocket disconnected!");
+                    self.journal.send(Event::Disconnected);
+                }
+                Ok(WebsocketEvent::Connected) => {
+                    log::trace!("wallet websocket (re)connected!");
+                    if self.track_prices {
+                        self.ws.request_init_data();
+                    }
+                    // a flapping connection must not run overlapping inits against the same trackers
+                    let init = CancellationToken::new();
+                    if let Some(previous) = current_init.replace(init.clone()) {
+                        if !previous.is_cancelled() {
+                            previous.cancel();
+                            self.cancel_syncs().await;
+                        }
+                    }
+                    let syncing_wallet = self.clone();
+                    let stop = stop.clone();
+                    self.spawn_task("address sync", async move {
+                        syncing_wallet.init_addresses(&init).await;
+                        if init.is_cancelled() || stop.is_cancelled() {
+                            log::trace!("wallet init superseded by a reconnect");
+                            return;
+                        }
+                        log::trace!("wallet initialized addresses");
+                        // catch anything missed while disconnected that the
+                        // incremental syncs could not have picked up
+                        syncing_wallet.reconcile(&init).await;
+                        syncing_wallet.refresh_outpoints().await;
+                        init.cancel();
+                    });
+                }
+                Ok(WebsocketEvent::Conversions(prices)) => {
+                    log::trace!("wallet received exchange rates");
+                    self.prices.update(prices);
+                }
+                Ok(WebsocketEvent::Error) => {
+                    log::trace!("wallet websocket threw an error");
+                }
+                Ok(WebsocketEvent::AddressEvent(address_event)) => {
+                    log::trace!("handling wallet ws event");
+                    self.handle_address_event(address_event, true).await;
+                    log::trace!("handled wallet ws event");
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    log::warn!("websocket event channel closed");
+                    break;
+                }
+                Err(e) => {
+                    log::warn!("unexpected websocket error {:?}", e);
+                }
+            }
+        }
+        stop.cancel();
+        if let Some(init) = current_init {
+            init.cancel();
+        }
+        log::trace!("wallet event loop ended");
     }
 
     #[must_use]
-    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
-        self.event_sender.subscribe()
+    pub fn subscribe(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.journal.subscribe()
     }
 
     pub async fn get_and_watch(&self, scriptpubkey: &ScriptBuf) -> Result<State, Error> {
@@ -180,47 +556,205 @@ impl Wallet {
     }
 
     pub async fn watch(&self, scriptpubkeys: &[ScriptBuf]) -> Result<Vec<State>, Error> {
-        log::trace!("wallet watch {:?}", scriptpubkeys);
-        self.ws.track_scriptpubkeys(scriptpubkeys);
+        self.watch_with_window(scriptpubkeys, HistoryWindow::Full).await
+    }
+
+    /// Watch scriptpubkeys, only fetching and keeping the part of their history inside `window`.
+    ///
+    /// Scriptpubkeys which are already watched keep their existing window.
+    /// If another call is still syncing one of them, this waits for that sync
+    /// to finish and then catches up on anything it missed.
+    ///
+    /// Watching and unwatching take effect in the order they are called.
+    /// If a scriptpubkey is unwatched before this returns, its state is returned
+    /// as it was when unwatched, with a `sync_status` of [`SyncStatus::Unwatched`].
+    ///
+    /// # Errors
+    ///
+    /// Sync failures are not returned as errors, but reported in the `sync_status`
+    /// of the returned states and retried in the background.
+    pub async fn watch_with_window(
+        &self,
+        scriptpubkeys: &[ScriptBuf],
+        window: HistoryWindow,
+    ) -> Result<Vec<State>, Error> {
+        Ok(self.watch_scriptpubkeys(scriptpubkeys, window, false).await)
+    }
 
-        let mut newly_synced = HashMap::new();
+    /// Watch scriptpubkeys, either permanently or for as long as a guard holds them,
+    /// returning their states
+    async fn watch_scriptpubkeys(
+        &self,
+        scriptpubkeys: &[ScriptBuf],
+        window: HistoryWindow,
+        guarded: bool,
+    ) -> Vec<State> {
+        log::trace!("wallet watch {:?} {:?}", scriptpubkeys, window);
 
+        let mut trackers = Vec::with_capacity(scriptpubkeys.len());
+        let mut to_sync = Vec::new();
         {
             let mut addresses = self.addresses.lock().await;
+            // (un)subscribe under the lock, so the websocket subscriptions
+            // always match the watched addresses
+            self.ws.track_scriptpubkeys(scriptpubkeys);
             for spk in scriptpubkeys {
-                if !addresses.contains_key(spk) {
-                    let tracker = Tracker::new(spk.clone(), self.event_sender.clone());
+                let tracker_arc = if let Some(tracker_arc) = addresses.get(spk) {
+                    let mut tracker = tracker_arc.lock().await;
+                    tracker.hold(guarded);
+                    if matches!(tracker.sync_status(), SyncStatus::Pending | SyncStatus::Syncing) {
+                        to_sync.push((spk.clone(), tracker_arc.clone()));
+                    }
+                    drop(tracker);
+                    tracker_arc.clone()
+                } else {
+                    let mut tracker = Tracker::new(spk.clone(), self.store.clone(), self.tip.clone(), self.journal.clone())
+                        .with_window(window);
+                    tracker.hold(guarded);
                     let tracker_arc = Arc::new(Mutex::new(tracker));
                     addresses.insert(spk.clone(), tracker_arc.clone());
-                    newly_synced.insert(spk.clone(), tracker_arc.clone());
-                }
+                    to_sync.push((spk.clone(), tracker_arc.clone()));
+                    tracker_arc
+                };
+                trackers.push(tracker_arc);
             }
-        };
+            drop(addresses);
+        }
 
-        for (spk, tracker_arc) in &newly_synced {
-            let _ = self.sync_address_history(spk, tracker_arc).await;
+        join_all(to_sync.iter().map(|(spk, tracker_arc)| {
+            self.sync_address(spk, tracker_arc)
+        })).await;
+
+        let mut results = Vec::with_capacity(trackers.len());
+        for tracker_arc in &trackers {
+            results.push(tracker_arc.lock().await.get_state());
         }
 
-        let addresses = self.addresses.lock().await;
-        let mut results = Vec::with_capacity(scriptpubkeys.len());
+        results
+    }
 
-        for spk in scriptpubkeys {
-            results.push(addresses.get(spk).expect("spk should exist in addresses, since we just inserted it").lock().await.get_state());
+    /// Watch scriptpubkeys until the returned guard is dropped
+    ///
+    /// Scriptpubkeys stay watched for as long as any guard holds them,
+    /// or until [`Wallet::unwatch`] if they are also watched permanently.
+    ///
+    /// # Errors
+    ///
+    /// See [`Wallet::watch`]
+    pub async fn watch_guarded(&self, scriptpubkeys: &[ScriptBuf]) -> Result<(Vec<State>, WatchGuard), Error> {
+        let states = self.watch_scriptpubkeys(scriptpubkeys, HistoryWindow::Full, true).await;
+        Ok((states, WatchGuard::new(self.clone(), scriptpubkeys.to_vec())))
+    }
+
+    /// Watch scriptpubkeys for `ttl` milliseconds, then unwatch them again
+    ///
+    /// # Errors
+    ///
+    /// See [`Wallet::watch`]
+    pub async fn watch_for(&self, scriptpubkeys: &[ScriptBuf], ttl: u64) -> Result<Vec<State>, Error> {
+        let (states, guard) = self.watch_guarded(scriptpubkeys).await?;
+        self.spawn_task("watch timeout", async move {
+            compat::sleep(ttl).await;
+            drop(guard);
+        });
+        Ok(states)
+    }
+
+    /// Fetch the history and balance of a scriptpubkey without subscribing to it
+    ///
+    /// If the scriptpubkey is already watched and synced, returns its current state.
+    /// The fetch holds a sync permit, like the syncs of watched addresses.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Cancelled`] if `cancel` is cancelled before the history is fetched,
+    /// or an error if fetching it fails
+    pub async fn query(&self, scriptpubkey: &ScriptBuf, cancel: &CancellationToken) -> Result<State, Error> {
+        if let Some(state) = self.get_address_state(scriptpubkey).await {
+            if state.sync_status == SyncStatus::Ready {
+                return Ok(state);
+            }
         }
 
-        Ok(results)
+        let permit = self.sync_permit(cancel).await?;
+        let transactions = self
+            .api
+            .fetch_address_history(
+                scriptpubkey,
+                api::HistoryLimit::default(),
+                &mut api::HistoryCursor::default(),
+                cancel,
+                &|_| {},
+            )
+            .await?;
+        drop(permit);
+
+        // nobody listens to a one-off tracker's events
+        let mut tracker = Tracker::new(
+            scriptpubkey.clone(),
+            self.store.clone(),
+            self.tip.clone(),
+            EventJournal::new(0),
+        );
+        tracker.apply_history(&transactions, None);
+        tracker.set_sync_status(SyncStatus::Ready);
+        let state = tracker.get_state();
+        drop(tracker);
+        // the one-off tracker's transactions are only referred to by the returned state
+        self.store.prune();
+        Ok(state)
     }
 
+    /// Stop watching scriptpubkeys
+    ///
+    /// In-progress syncs of these scriptpubkeys are cancelled,
+    /// and no further events are emitted for them.
+    /// Scriptpubkeys still held by a [`WatchGuard`] stay watched until it is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Reserved for failing to unsubscribe over the websocket,
+    /// which is currently only logged, so this never returns an error
     pub async fn unwatch(&self, scriptpubkeys: &[ScriptBuf]) -> Result<(), Error> {
+        self.release_watches(scriptpubkeys, false).await;
+        Ok(())
+    }
+
+    /// Drop a permanent or guarded watch of each of `scriptpubkeys`,
+    /// unwatching those nothing else holds
+    pub(crate) async fn release_watches(&self, scriptpubkeys: &[ScriptBuf], guarded: bool) {
         let mut addresses = self.addresses.lock().await;
 
+        let mut unwatched = Vec::new();
         for spk in scriptpubkeys {
-            addresses.remove(spk);
+            let Some(tracker_arc) = addresses.get(spk).cloned() else {
+                continue;
+            };
+            let mut tracker = tracker_arc.lock().await;
+            if tracker.release(guarded) {
+                addresses.remove(spk);
+                tracker.detach();
+                unwatched.push(spk.clone());
+            }
+            drop(tracker);
         }
+        unwatched.retain(|spk| !self.internal.contains(spk));
+        self.ws.untrack_scriptpubkeys(&unwatched);
+        drop(addresses);
+        self.store.prune();
+    }
 
-        self.ws.untrack_scriptpubkeys(scriptpubkeys);
-
-        Ok(())
+    /// Turn guarded watches of `scriptpubkeys` into permanent ones
+    pub(crate) async fn keep_watches(&self, scriptpubkeys: &[ScriptBuf]) {
+        let addresses = self.addresses.lock().await;
+        for spk in scriptpubkeys {
+            if let Some(tracker_arc) = addresses.get(spk) {
+                let mut tracker = tracker_arc.lock().await;
+                tracker.hold(false);
+                tracker.release(true);
+            }
+        }
+        drop(addresses);
     }
 
     pub async fn get_state(&self) -> Vec<State> {
@@ -230,6 +764,7 @@ impl Wallet {
             let tracker = tracker_arc.lock().await;
             results.push(tracker.get_state());
         }
+        drop(addresses);
 
         results
     }
@@ -243,16 +778,56 @@ impl Wallet {
         }
     }
 
-    async fn handle_address_event(&self, event: address::Event, realtime: bool) {
+    /// Wallet-wide view of all watched addresses,
+    /// with transactions deduplicated and valued for the wallet as a whole
+    pub async fn summary(&self) -> Summary {
+        Summary::from_states(&self.get_state().await)
+    }
+
+    /// Height of the best block the wallet has seen, if any
+    #[must_use]
+    pub fn tip_height(&self) -> Option<u32> {
+        self.tip.height()
+    }
+
+    /// How many watched addresses have finished syncing
+    pub async fn progress(&self) -> Progress {
         let addresses = self.addresses.lock().await;
+        let mut progress = Progress {
+            addresses_ready: 0,
+            addresses_total: addresses.len(),
+        };
+        for tracker_arc in addresses.values() {
+            if !tracker_arc.lock().await.is_loading() {
+                progress.addresses_ready += 1;
+            }
+        }
+        drop(addresses);
+
+        progress
+    }
+
+    async fn handle_address_event(&self, event: address::Event, realtime: bool) {
         match &event {
             address::Event::Mempool(scriptpubkey, _)
             | address::Event::Confirmed(scriptpubkey, _)
             | address::Event::Removed(scriptpubkey, _) => {
-                if let Some(tracker_arc) = addresses.get(scriptpubkey) {
-                    let mut tracker = tracker_arc.lock().await;
-                    tracker.process_event(event, realtime);
-                } else {
+                let tracker_arc_option = {
+                    let addresses = self.addresses.lock().await;
+                    addresses.get(scriptpubkey).cloned()
+                };
+                self.internal.send(WatchEvent::Tx(event.clone()));
+                self.check_outpoint_spends(&event);
+                if let Some(tracker_arc) = tracker_arc_option {
+                    let entered_mempool = realtime && matches!(event, address::Event::Mempool(..));
+                    tracker_arc.lock().await.process_event(event, realtime);
+                    if entered_mempool {
+                        let wallet = self.clone();
+                        self.spawn_task("package refresh", async move {
+                            wallet.refresh_packages(&tracker_arc).await;
+                        });
+                    }
+                } else if !self.internal.contains(scriptpubkey) {
                     log::warn!("handling event for unknown scriptpubkey: {}", scriptpubkey);
                 }
             }
@@ -264,81 +839,435 @@ impl Wallet {
         scriptpubkey: &ScriptBuf,
         tracker_arc: &Arc<Mutex<Tracker>>,
     ) -> Result<State, Error> {
-        let mut tracker = tracker_arc.lock().await;
+        let (cancel, sync_lock) = {
+            let tracker = tracker_arc.lock().await;
+            (tracker.sync_token(), tracker.sync_lock())
+        };
+
+        // syncs of the same address run one after another,
+        // each picking up where the previous one left off
+        let _syncing = tokio::select! {
+            biased;
+            () = cancel.cancelled() => return Err(Error::Cancelled),
+            guard = sync_lock.lock() => guard,
+        };
 
-        tracker.set_loading(true);
+        // each sync has at most one history request in flight at a time,
+        // so limiting concurrent syncs also limits concurrent requests
+        let permit = self.sync_permit(&cancel).await?;
 
-        let initial_state = tracker.get_state();
+        // realtime events are queued by the tracker while it is loading,
+        // so the lock need not be held across network calls
+        let (initial_state, window, mut cursor, full_resync) = {
+            let mut tracker = tracker_arc.lock().await;
+            tracker.set_sync_status(SyncStatus::Syncing);
+            (tracker.get_state(), tracker.window(), tracker.take_history_cursor(), tracker.needs_full_resync())
+        };
 
         log::trace!(
             "syncing address from initial state: {:?}",
             initial_state.clone().transactions.len()
         );
 
+        // a full resync does not trust any of the transactions already known
         let (last_txid, last_height) = initial_state
             .transactions
             .iter()
             .rev()
-            .find(|tx| tx.status.confirmed)
+            .find(|tx| tx.status.confirmed && !full_resync)
             .map_or((None, None), |tx| (Some(tx.txid), tx.status.block_height));
 
-        let initial_transactions = self
-            .api
-            .fetch_address_history(scriptpubkey, last_txid, last_height)
-            .await?;
+        let on_progress = |cursor: &api::HistoryCursor| {
+            if cancel.is_cancelled() {
+                return;
+            }
+            self.journal.send(Event::SyncProgress {
+                scriptpubkey: scriptpubkey.clone(),
+                pages_fetched: cursor.pages_fetched,
+                txs_fetched: cursor.fetched.len(),
+                oldest_height_reached: cursor.oldest_height_reached(),
+            });
+        };
+
+        let limit = window.history_limit(last_txid, last_height);
+        let fetch_result = self
+            .fetch_history(scriptpubkey, window, limit, &mut cursor, &cancel, &on_progress)
+            .await;
+        // package information is best-effort, so other syncs need not wait for it
+        drop(permit);
+        let packages = match &fetch_result {
+            Ok((transactions, _)) => {
+                let unconfirmed = transactions.iter().filter(|tx| !tx.status.confirmed).map(|tx| tx.txid);
+                self.fetch_packages(unconfirmed, &cancel).await
+            }
+            Err(_) => HashMap::new(),
+        };
+
+        let mut tracker = tracker_arc.lock().await;
+
+        // the address may have been unwatched while the last request was in flight
+        let fetch_result = if cancel.is_cancelled() {
+            Err(api::Error::Cancelled)
+        } else {
+            fetch_result
+        };
 
-        let mut fetched_txids = HashSet::new();
-        for tx in &initial_transactions {
-            fetched_txids.insert(tx.txid);
+        let (initial_transactions, confirmed) = match fetch_result {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                // keep the pages fetched so far, so the next sync can pick up from here
+                tracker.set_history_cursor(cursor);
+                let err = Error::from(e);
+                if matches!(err, Error::Cancelled) {
+                    tracker.set_sync_status(SyncStatus::Pending);
+                } else {
+                    tracker.set_sync_status(SyncStatus::Failed(err.to_string()));
+                }
+                return Err(err);
+            }
+        };
+
+        log::trace!("processing {} transactions", initial_transactions.len());
+
+        // a bounded fetch may stop short of the transactions we already know about,
+        // so only transactions newer than the oldest one fetched can have disappeared
+        let known_height = if window.is_bounded() {
+            let oldest_fetched_height = initial_transactions
+                .iter()
+                .find(|tx| tx.status.confirmed)
+                .and_then(|tx| tx.status.block_height);
+            last_height.max(oldest_fetched_height)
+        } else {
+            last_height
+        };
+        tracker.apply_history(&initial_transactions, known_height);
+        if let Some(confirmed) = confirmed {
+            tracker.set_confirmed(confirmed);
+        }
+        tracker.set_packages(packages);
+        tracker.full_resync_done();
+
+        tracker.set_sync_status(SyncStatus::Ready);
+
+        self.journal.send(Event::AddressReady(scriptpubkey.clone()));
+
+        Ok(tracker.get_state())
+    }
+
+    /// Fetch an address history, along with the confirmed totals
+    /// from the REST address summary if the history window is bounded
+    async fn fetch_history(
+        &self,
+        scriptpubkey: &ScriptBuf,
+        window: HistoryWindow,
+        limit: api::HistoryLimit,
+        cursor: &mut api::HistoryCursor,
+        cancel: &CancellationToken,
+        on_progress: &(impl Fn(&api::HistoryCursor) + Sync),
+    ) -> Result<(Vec<Tx>, Option<TxoStats>), api::Error> {
+        if !window.is_bounded() {
+            let transactions = self
+                .api
+                .fetch_address_history(scriptpubkey, limit, cursor, cancel, on_progress)
+                .await?;
+            return Ok((transactions, None));
         }
 
-        for tx in initial_state.transactions.iter().rev().take_while(|tx| {
-            !tx.status.confirmed || last_height.is_none() || tx.status.block_height > last_height
-        }) {
-            if !fetched_txids.contains(&tx.txid) {
-                tracker
-                    .process_event(
-                        address::Event::Removed(scriptpubkey.clone(), tx.clone()),
-                        false,
-                    );
+        // fetch the summary either side of the history, so the balance
+        // is known to match the transactions that were fetched
+        let mut before = self.api.scripthash_stats(scriptpubkey).await?;
+        let mut attempts = 1;
+        loop {
+            let transactions = self
+                .api
+                .fetch_address_history(scriptpubkey, limit, cursor, cancel, on_progress)
+                .await?;
+            let after = self.api.scripthash_stats(scriptpubkey).await?;
+            if after == before || attempts >= BOUNDED_SYNC_ATTEMPTS {
+                return Ok((transactions, Some(after.chain_stats)));
             }
+            log::trace!("address summary changed while fetching history, refetching {scriptpubkey}");
+            before = after;
+            attempts += 1;
         }
+    }
 
-        log::trace!("processing {} transactions", initial_transactions.len());
+    /// Fetch package information for the unconfirmed transactions `txids`
+    ///
+    /// This is best-effort: transactions the backend has no package information for
+    /// (or which have confirmed in the meantime) are left out.
+    async fn fetch_packages(
+        &self,
+        txids: impl Iterator<Item = Txid>,
+        cancel: &CancellationToken,
+    ) -> HashMap<Txid, CpfpInfo> {
+        let fetches: Vec<_> = txids.map(|txid| self.fetch_package(txid, cancel)).collect();
+        let packages: Vec<_> = futures_util::stream::iter(fetches)
+            .buffer_unordered(MAX_CONCURRENT_PACKAGE_FETCHES)
+            .collect()
+            .await;
+        packages.into_iter().flatten().collect()
+    }
 
-        for tx in &initial_transactions {
-            if tx.status.confirmed {
-                tracker
-                    .process_event(
-                        address::Event::Confirmed(scriptpubkey.clone(), tx.clone()),
-                        false,
-                    );
-            } else {
-                tracker
-                    .process_event(
-                        address::Event::Mempool(scriptpubkey.clone(), tx.clone()),
-                        false,
-                    );
+    async fn fetch_package(&self, txid: Txid, cancel: &CancellationToken) -> Option<(Txid, CpfpInfo)> {
+        if cancel.is_cancelled() {
+            return None;
+        }
+        match self.api.get_cpfp_info(&txid).await {
+            Ok(package) => Some((txid, package)),
+            Err(e) => {
+                log::debug!("no package information for {txid}: {e:?}");
+                None
             }
         }
+    }
 
-        tracker.set_loading(false);
+    /// Refetch package information for the unconfirmed transactions of an address,
+    /// since a transaction entering the mempool may have joined their packages
+    async fn refresh_packages(&self, tracker_arc: &Arc<Mutex<Tracker>>) {
+        let (txids, cancel) = {
+            let tracker = tracker_arc.lock().await;
+            if tracker.is_loading() {
+                // the sync in progress fetches them anyway
+                return;
+            }
+            (tracker.unconfirmed_txids(), tracker.sync_token())
+        };
+        if txids.is_empty() {
+            return;
+        }
+        let packages = self.fetch_packages(txids.into_iter(), &cancel).await;
+        if !cancel.is_cancelled() {
+            tracker_arc.lock().await.set_packages(packages);
+        }
+    }
 
-        let _ = self.event_sender.send(Event::AddressReady(scriptpubkey.clone()));
+    /// Sync an address history, retrying in the background if it fails
+    async fn sync_address(
+        &self,
+        scriptpubkey: &ScriptBuf,
+        tracker_arc: &Arc<Mutex<Tracker>>,
+    ) -> Result<State, Error> {
+        let result = self.sync_address_history(scriptpubkey, tracker_arc).await;
+        if let Err(e) = &result {
+            if !matches!(e, Error::Cancelled) {
+                self.notify_sync_failed(scriptpubkey, e, 1);
+                let wallet = self.clone();
+                let scriptpubkey = scriptpubkey.clone();
+                let tracker_arc = tracker_arc.clone();
+                self.spawn_task("sync retry", async move {
+                    wallet.retry_sync(&scriptpubkey, &tracker_arc).await;
+                });
+            }
+        }
+        result
+    }
 
-        Ok(tracker.get_state())
+    /// Keep retrying a failed address sync with exponential backoff,
+    /// until it succeeds, the address is unwatched or the wallet disconnects
+    async fn retry_sync(&self, scriptpubkey: &ScriptBuf, tracker_arc: &Arc<Mutex<Tracker>>) {
+        let cancel = tracker_arc.lock().await.sync_token();
+        let mut attempts: u32 = 1;
+        loop {
+            let delay = SYNC_RETRY_INITIAL_DELAY
+                .saturating_mul(1 << (attempts - 1).min(16))
+                .min(SYNC_RETRY_MAX_DELAY);
+            log::trace!("retrying address sync in {delay}ms {scriptpubkey}");
+            tokio::select! {
+                biased;
+                () = cancel.cancelled() => return,
+                () = compat::sleep(delay) => {}
+            }
+
+            // the address may have been resynced in the meantime, e.g. after a reconnect
+            if !matches!(tracker_arc.lock().await.sync_status(), SyncStatus::Failed(_)) {
+                return;
+            }
+
+            match self.sync_address_history(scriptpubkey, tracker_arc).await {
+                Ok(_) | Err(Error::Cancelled) => return,
+                Err(e) => {
+                    attempts += 1;
+                    self.notify_sync_failed(scriptpubkey, &e, attempts);
+                }
+            }
+        }
+    }
+
+    fn notify_sync_failed(&self, scriptpubkey: &ScriptBuf, error: &Error, attempts: u32) {
+        log::warn!("failed to sync address {scriptpubkey} (attempt {attempts}): {error}");
+        self.journal.send(Event::AddressSyncFailed {
+            scriptpubkey: scriptpubkey.clone(),
+            error: error.to_string(),
+            attempts,
+        });
+    }
+
+    /// Check every address against the REST address summary every `reconcile_interval`,
+    /// until `stop` is cancelled
+    async fn reconcile_periodically(&self, stop: &CancellationToken) {
+        loop {
+            tokio::select! {
+                biased;
+                () = stop.cancelled() => return,
+                () = compat::sleep(self.reconcile_interval) => {}
+            }
+            self.reconcile(stop).await;
+        }
+    }
+
+    /// Fetch the height of the best block, needed to tell when coinbase outputs mature
+    async fn refresh_tip(&self) {
+        match self.api.get_height().await {
+            Ok(height) => self.tip.update(height),
+            Err(e) => log::warn!("failed to fetch the chain tip: {e:?}"),
+        }
+    }
+
+    /// Refresh the chain tip every [`TIP_REFRESH_INTERVAL`], until `stop` is cancelled
+    async fn refresh_tip_periodically(&self, stop: &CancellationToken) {
+        loop {
+            tokio::select! {
+                biased;
+                () = stop.cancelled() => return,
+                () = compat::sleep(TIP_REFRESH_INTERVAL) => {}
+            }
+            self.refresh_tip().await;
+        }
+    }
+
+    /// Check every synced address against the REST address summary,
+    /// resyncing any which have drifted from the server, until `cancel` is cancelled
+    async fn reconcile(&self, cancel: &CancellationToken) {
+        let trackers: Vec<(ScriptBuf, Arc<Mutex<Tracker>>)> = {
+            let addresses = self.addresses.lock().await;
+            addresses
+                .iter()
+                .map(|(spk, tracker_arc)| (spk.clone(), tracker_arc.clone()))
+                .collect()
+        };
+        log::trace!("reconciling {} addresses", trackers.len());
+
+        // requests take sync permits, so a large wallet doesn't crowd out other requests
+        join_all(trackers.iter().map(|(scriptpubkey, tracker_arc)| async move {
+            if let Err(e) = self.reconcile_address(scriptpubkey, tracker_arc, cancel).await {
+                if !matches!(e, Error::Cancelled) {
+                    log::warn!("failed to reconcile address {scriptpubkey}: {e}");
+                }
+            }
+        }))
+        .await;
+    }
+
+    /// Wait for a sync permit, unless `cancel` is cancelled first
+    async fn sync_permit(&self, cancel: &CancellationToken) -> Result<SemaphorePermit<'_>, Error> {
+        tokio::select! {
+            biased;
+            () = cancel.cancelled() => Err(Error::Cancelled),
+            permit = self.sync_permits.acquire() => Ok(permit.expect("sync semaphore is never closed")),
+        }
+    }
+
+    /// Fetch the REST address summary, holding a sync permit for the request
+    async fn reconcile_stats(&self, scriptpubkey: &ScriptBuf, cancel: &CancellationToken) -> Result<AddressStats, Error> {
+        let _permit = self.sync_permit(cancel).await?;
+        Ok(self.api.scripthash_stats(scriptpubkey).await?)
+    }
+
+    /// Compare an address with the REST address summary, resyncing it if they disagree.
+    ///
+    /// A mismatch must still be there after [`RECONCILE_RECHECK_DELAY`],
+    /// since the websocket may not have delivered the latest transactions yet.
+    async fn reconcile_address(
+        &self,
+        scriptpubkey: &ScriptBuf,
+        tracker_arc: &Arc<Mutex<Tracker>>,
+        cancel: &CancellationToken,
+    ) -> Result<(), Error> {
+        let mut remote = self.reconcile_stats(scriptpubkey, cancel).await?;
+        let mut checks = 1;
+        let local = loop {
+            let tracker = tracker_arc.lock().await;
+            // syncing addresses are about to be brought up to date anyway
+            if *tracker.sync_status() != SyncStatus::Ready {
+                return Ok(());
+            }
+            let (local, agrees) = (tracker.stats(), tracker.agrees_with(&remote));
+            drop(tracker);
+            if agrees {
+                return Ok(());
+            }
+            if checks >= 2 {
+                break local;
+            }
+            tokio::select! {
+                biased;
+                () = cancel.cancelled() => return Err(Error::Cancelled),
+                () = compat::sleep(RECONCILE_RECHECK_DELAY) => {}
+            }
+            remote = self.reconcile_stats(scriptpubkey, cancel).await?;
+            checks += 1;
+        };
+
+        log::warn!("address out of sync with the server {scriptpubkey}: {local:?} != {remote:?}");
+        {
+            let mut tracker = tracker_arc.lock().await;
+            if tracker.is_unwatched() {
+                return Ok(());
+            }
+            tracker.request_full_resync();
+        }
+        self.journal.send(Event::Inconsistency {
+            scriptpubkey: scriptpubkey.clone(),
+            local,
+            remote,
+        });
+        // failures are retried in the background, and reported as events
+        let _ = self.sync_address(scriptpubkey, tracker_arc).await;
+        Ok(())
     }
 
-    async fn init_addresses(&self) {
+    /// Interrupt every in-progress address sync
+    async fn cancel_syncs(&self) {
         let addresses = self.addresses.lock().await;
-        log::trace!("(re)initialising {} addresses", addresses.len());
+        for tracker_arc in addresses.values() {
+            tracker_arc.lock().await.cancel_sync();
+        }
+    }
 
-        let spks: Vec<ScriptBuf> = addresses.keys().cloned().collect();
+    /// Resync every watched address after a (re)connect, unless `cancel` is cancelled first
+    async fn init_addresses(&self, cancel: &CancellationToken) {
+        let trackers: Vec<(ScriptBuf, Arc<Mutex<Tracker>>)> = {
+            let addresses = self.addresses.lock().await;
+            addresses
+                .iter()
+                .map(|(spk, tracker_arc)| (spk.clone(), tracker_arc.clone()))
+                .collect()
+        };
+        log::trace!("(re)initialising {} addresses", trackers.len());
+        self.journal.send(Event::Initializing);
+
+        let mut spks: Vec<ScriptBuf> = trackers.iter().map(|(spk, _)| spk.clone()).collect();
+        spks.extend(self.internal.scriptpubkeys());
         self.ws.track_scriptpubkeys(&spks);
+        self.internal.send(WatchEvent::Reconnected);
+
+        self.refresh_tip().await;
 
-        // TODO: parallelize this
-        for (scriptpubkey, tracker) in &*addresses {
-            let _ = self.sync_address_history(scriptpubkey, tracker).await;
+        if cancel.is_cancelled() {
+            return;
         }
+
+        // start queueing realtime events for every address before any sync begins
+        for (_, tracker_arc) in &trackers {
+            let mut tracker = tracker_arc.lock().await;
+            if *tracker.sync_status() != SyncStatus::Syncing {
+                tracker.set_sync_status(SyncStatus::Pending);
+            }
+        }
+
+        join_all(trackers.iter().map(|(scriptpubkey, tracker_arc)| {
+            self.sync_address(scriptpubkey, tracker_arc)
+        })).await;
     }
 }
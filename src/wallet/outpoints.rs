@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use bitcoin::{OutPoint, ScriptBuf, Txid};
+use esplora_client::{OutputStatus, Tx, TxStatus};
+use serde::{Deserialize, Serialize};
+
+use super::address;
+use super::internal::InternalWatch;
+use super::{schema, Error, Event, Wallet};
+
+/// The transaction spending a watched outpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutpointSpend {
+    pub spending_txid: Txid,
+    /// index of the spending input
+    pub vin: u32,
+    #[serde(with = "schema::status")]
+    pub status: TxStatus,
+}
+
+impl OutpointSpend {
+    fn from_tx(tx: &Tx, outpoint: &OutPoint) -> Option<Self> {
+        let vin = tx.vin.iter().position(|vin| vin.txid == outpoint.txid && vin.vout == outpoint.vout)?;
+        Some(Self {
+            spending_txid: tx.txid,
+            vin: u32::try_from(vin).ok()?,
+            status: tx.status.clone(),
+        })
+    }
+
+    fn from_output_status(status: OutputStatus) -> Option<Self> {
+        if !status.spent {
+            return None;
+        }
+        Some(Self {
+            spending_txid: status.txid?,
+            vin: u32::try_from(status.vin?).ok()?,
+            status: status.status?,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct WatchedOutpoint {
+    /// holds the subscription to the outpoint's scriptpubkey
+    watch: Option<InternalWatch>,
+    spend: Option<OutpointSpend>,
+}
+
+/// Outpoints watched for spends, along with the last spend seen of each
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OutpointWatcher {
+    watched: Arc<Mutex<HashMap<OutPoint, WatchedOutpoint>>>,
+}
+
+impl OutpointWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start watching `outpoint`, returning false if it already was
+    fn insert(&self, outpoint: OutPoint) -> bool {
+        let mut watched = self.watched.lock().unwrap_or_else(PoisonError::into_inner);
+        if watched.contains_key(&outpoint) {
+            return false;
+        }
+        watched.insert(outpoint, WatchedOutpoint { watch: None, spend: None });
+        true
+    }
+
+    /// Hold on to the watch of the scriptpubkey of `outpoint` until it is unwatched,
+    /// or drop it straight away if it has been unwatched in the meantime
+    fn hold(&self, outpoint: &OutPoint, watch: InternalWatch) {
+        let mut watched = self.watched.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(watched) = watched.get_mut(outpoint) {
+            watched.watch = Some(watch);
+        }
+    }
+
+    /// Stop watching `outpoint`, returning the watch of its scriptpubkey
+    fn remove(&self, outpoint: &OutPoint) -> Option<InternalWatch> {
+        let mut watched = self.watched.lock().unwrap_or_else(PoisonError::into_inner);
+        watched.remove(outpoint)?.watch
+    }
+
+    pub fn spend(&self, outpoint: &OutPoint) -> Option<OutpointSpend> {
+        let watched = self.watched.lock().unwrap_or_else(PoisonError::into_inner);
+        watched.get(outpoint).and_then(|watched| watched.spend.clone())
+    }
+
+    fn outpoints(&self) -> Vec<OutPoint> {
+        let watched = self.watched.lock().unwrap_or_else(PoisonError::into_inner);
+        watched.keys().copied().collect()
+    }
+
+    /// Record the current spend of `outpoint`, returning it if it is new
+    /// or has changed confirmation status since it was last seen
+    fn update(&self, outpoint: &OutPoint, spend: Option<OutpointSpend>) -> Option<OutpointSpend> {
+        let previous = {
+            let mut watched = self.watched.lock().unwrap_or_else(PoisonError::into_inner);
+            std::mem::replace(&mut watched.get_mut(outpoint)?.spend, spend.clone())
+        };
+        let changed = match (&previous, &spend) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(old), Some(new)) => old.spending_txid != new.spending_txid || old.status.confirmed != new.status.confirmed,
+        };
+        spend.filter(|_| changed)
+    }
+
+    /// Apply an address event to the outpoints its transaction spends,
+    /// returning the spends which are new or newly confirmed
+    fn apply(&self, event: &address::Event) -> Vec<(OutPoint, OutpointSpend)> {
+        let tx = event.tx();
+        let mut spends = Vec::new();
+        for vin in &tx.vin {
+            let outpoint = OutPoint { txid: vin.txid, vout: vin.vout };
+            let spend = match event {
+                address::Event::Mempool(..) | address::Event::Confirmed(..) => OutpointSpend::from_tx(tx, &outpoint),
+                // the spend was dropped, unless another one has been seen since
+                address::Event::Removed(..) => match self.spend(&outpoint) {
+                    Some(spend) if spend.spending_txid != tx.txid => Some(spend),
+                    _ => None,
+                },
+            };
+            if let Some(spend) = self.update(&outpoint, spend) {
+                spends.push((outpoint, spend));
+            }
+        }
+        spends
+    }
+}
+
+impl Wallet {
+    /// Watch an outpoint, emitting an [`Event::OutpointSpent`] when a spend of it
+    /// appears in the mempool and again when that spend confirms
+    ///
+    /// The outpoint's scriptpubkey is subscribed to over the websocket,
+    /// without becoming a wallet address.
+    /// Returns the current spend of the outpoint, if any, which is not reported as an event.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Missing`] if the outpoint does not exist,
+    /// or an error if it could not be looked up, in which case it is not left watched
+    pub async fn watch_outpoint(&self, outpoint: OutPoint) -> Result<Option<OutpointSpend>, Error> {
+        let tx = self.api.get_tx(&outpoint.txid).await?.ok_or(Error::Missing)?;
+        let output = usize::try_from(outpoint.vout).ok().and_then(|vout| tx.output.get(vout));
+        let scriptpubkey = output.ok_or(Error::Missing)?.script_pubkey.clone();
+
+        if !self.outpoints.insert(outpoint) {
+            return Ok(self.outpoints.spend(&outpoint));
+        }
+        match self.fetch_outpoint_spend(outpoint, scriptpubkey).await {
+            Ok(spend) => Ok(spend),
+            Err(e) => {
+                self.unwatch_outpoint(&outpoint).await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Subscribe to the scriptpubkey of a newly watched outpoint and record its current spend
+    async fn fetch_outpoint_spend(&self, outpoint: OutPoint, scriptpubkey: ScriptBuf) -> Result<Option<OutpointSpend>, Error> {
+        let watch = self.watch_internal(&scriptpubkey).await;
+        self.outpoints.hold(&outpoint, watch);
+        let status = self.api.get_tx_outspends(&outpoint.txid).await?.into_iter().nth(outpoint.vout as usize);
+        self.outpoints.update(&outpoint, status.and_then(OutpointSpend::from_output_status));
+        Ok(self.outpoints.spend(&outpoint))
+    }
+
+    /// Stop watching an outpoint, and its scriptpubkey unless something else still watches it
+    ///
+    /// # Errors
+    ///
+    /// Like [`Wallet::unwatch`], this never returns an error
+    pub async fn unwatch_outpoint(&self, outpoint: &OutPoint) -> Result<(), Error> {
+        if let Some(watch) = self.outpoints.remove(outpoint) {
+            watch.release().await;
+        }
+        Ok(())
+    }
+
+    /// Report spends of watched outpoints by the transaction of an address event
+    pub(super) fn check_outpoint_spends(&self, event: &address::Event) {
+        for (outpoint, spend) in self.outpoints.apply(event) {
+            self.report_outpoint_spend(outpoint, spend);
+        }
+    }
+
+    /// Fetch the current spend of every watched outpoint,
+    /// catching up on anything missed while disconnected
+    pub(super) async fn refresh_outpoints(&self) {
+        let mut by_txid: HashMap<Txid, Vec<OutPoint>> = HashMap::new();
+        for outpoint in self.outpoints.outpoints() {
+            by_txid.entry(outpoint.txid).or_default().push(outpoint);
+        }
+        for (txid, outpoints) in by_txid {
+            let statuses = match self.api.get_tx_outspends(&txid).await {
+                Ok(statuses) => statuses,
+                Err(e) => {
+                    log::warn!("failed to refresh spends of outputs of {txid}: {e:?}");
+                    continue;
+                }
+            };
+            for outpoint in outpoints {
+                let status = statuses.get(outpoint.vout as usize).cloned();
+                if let Some(spend) = self.outpoints.update(&outpoint, status.and_then(OutpointSpend::from_output_status)) {
+                    self.report_outpoint_spend(outpoint, spend);
+                }
+            }
+        }
+    }
+
+    fn report_outpoint_spend(&self, outpoint: OutPoint, spend: OutpointSpend) {
+        self.journal.send(Event::OutpointSpent {
+            outpoint,
+            spending_txid: spend.spending_txid,
+            status: spend.status,
+        });
+    }
+}
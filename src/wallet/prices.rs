@@ -0,0 +1,255 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use bitcoin::amount::serde::as_sat;
+use bitcoin::{SignedAmount, Txid};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use super::address::{flows, signed, Balance, State};
+use super::{Error, Wallet};
+use crate::api::prices::Prices;
+
+/// Width in seconds of the time buckets past prices are looked up and cached by,
+/// matching the hourly resolution of the backend's price history
+const PRICE_BUCKET: u64 = 3_600;
+
+/// Maximum number of past prices kept, the oldest looked up being dropped first
+const MAX_CACHED_PRICES: usize = 10_000;
+
+/// Maximum number of past price requests in flight for one valuation
+const MAX_CONCURRENT_PRICE_FETCHES: usize = 4;
+
+/// A [`Balance`] valued in a fiat currency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FiatBalance {
+    pub currency: String,
+    /// price of one bitcoin the balance was valued at
+    pub rate: f64,
+    /// when that price was fetched, in seconds since the epoch
+    pub time: u64,
+    pub confirmed: f64,
+    pub trusted_pending: f64,
+    pub untrusted_pending: f64,
+    pub outgoing_pending: f64,
+    pub immature: f64,
+    /// see [`Balance::total`]
+    pub total: f64,
+}
+
+/// What a confirmed transaction was worth to an address when it confirmed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FiatValue {
+    pub txid: Txid,
+    /// in seconds since the epoch
+    pub block_time: u64,
+    /// value received by the address minus value spent from it
+    #[serde(with = "as_sat")]
+    pub value: SignedAmount,
+    pub currency: String,
+    /// price of one bitcoin closest to the start of the hour `block_time` falls in
+    pub rate: f64,
+    pub fiat_value: f64,
+}
+
+/// Latest exchange rates, kept up to date from the websocket,
+/// along with the past rates looked up most recently
+#[derive(Debug, Clone)]
+pub(crate) struct PriceFeed {
+    latest: Arc<watch::Sender<Option<Prices>>>,
+    historical: Arc<Mutex<HistoricalRates>>,
+}
+
+/// Past rates keyed by currency and the start of their time bucket
+#[derive(Debug, Default)]
+struct HistoricalRates {
+    rates: HashMap<(String, u64), f64>,
+    /// keys in the order they were cached, for eviction
+    order: VecDeque<(String, u64)>,
+}
+
+/// Start of the time bucket `time` falls in
+const fn bucket(time: u64) -> u64 {
+    time - time % PRICE_BUCKET
+}
+
+impl PriceFeed {
+    pub fn new() -> Self {
+        Self {
+            latest: Arc::new(watch::channel(None).0),
+            historical: Arc::new(Mutex::new(HistoricalRates::default())),
+        }
+    }
+
+    /// Replace the latest prices, unless `prices` are older
+    pub fn update(&self, prices: Prices) {
+        self.latest.send_if_modified(|latest| {
+            if latest.as_ref().is_some_and(|latest| latest.time > prices.time) {
+                return false;
+            }
+            *latest = Some(prices);
+            true
+        });
+    }
+
+    pub fn latest(&self) -> Option<Prices> {
+        self.latest.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Option<Prices>> {
+        self.latest.subscribe()
+    }
+
+    fn cached(&self, currency: &str, time: u64) -> Option<f64> {
+        let key = (currency.to_string(), bucket(time));
+        self.historical.lock().unwrap_or_else(PoisonError::into_inner).rates.get(&key).copied()
+    }
+
+    fn cache(&self, currency: &str, time: u64, rate: f64) {
+        let key = (currency.to_string(), bucket(time));
+        let mut historical = self.historical.lock().unwrap_or_else(PoisonError::into_inner);
+        if historical.rates.insert(key.clone(), rate).is_none() {
+            historical.order.push_back(key);
+        }
+        while historical.order.len() > MAX_CACHED_PRICES {
+            if let Some(oldest) = historical.order.pop_front() {
+                historical.rates.remove(&oldest);
+            }
+        }
+        drop(historical);
+    }
+}
+
+impl FiatBalance {
+    fn new(balance: &Balance, prices: &Prices, currency: &str) -> Option<Self> {
+        let rate = prices.rate(currency)?;
+        let value = |amount| signed(amount).to_btc() * rate;
+        Some(Self {
+            currency: currency.to_string(),
+            rate,
+            time: prices.time,
+            confirmed: value(balance.confirmed),
+            trusted_pending: value(balance.trusted_pending),
+            untrusted_pending: value(balance.untrusted_pending),
+            outgoing_pending: value(balance.outgoing_pending),
+            immature: value(balance.immature),
+            total: balance.total().to_btc() * rate,
+        })
+    }
+}
+
+impl Wallet {
+    /// The latest exchange rates, if any have been received yet
+    ///
+    /// Rates are only kept up to date while connected with [`Options::track_prices`](super::Options::track_prices) set.
+    #[must_use]
+    pub fn prices(&self) -> Option<Prices> {
+        self.prices.latest()
+    }
+
+    /// Receive the latest exchange rates whenever they change
+    #[must_use]
+    pub fn subscribe_prices(&self) -> watch::Receiver<Option<Prices>> {
+        self.prices.subscribe()
+    }
+
+    /// Value a balance in `currency` at the latest exchange rate,
+    /// if there is one for that currency
+    #[must_use]
+    pub fn fiat_balance(&self, balance: &Balance, currency: &str) -> Option<FiatBalance> {
+        FiatBalance::new(balance, &self.prices.latest()?, currency)
+    }
+
+    /// Value each confirmed transaction of an address in `currency`,
+    /// at the exchange rate closest to the hour it confirmed in
+    ///
+    /// Unconfirmed transactions, and those the backend has no price for, are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the historical price requests fails
+    pub async fn fiat_values(&self, state: &State, currency: &str) -> Result<Vec<FiatValue>, Error> {
+        let confirmed: Vec<_> = state
+            .transactions
+            .iter()
+            .filter(|tx| tx.status.confirmed)
+            .filter_map(|tx| Some((tx, tx.status.block_time?)))
+            .collect();
+
+        // transactions confirmed in the same hour share a single lookup
+        let buckets: BTreeSet<u64> = confirmed.iter().map(|(_, block_time)| bucket(*block_time)).collect();
+        let lookups: Vec<_> = buckets
+            .into_iter()
+            .map(|time| async move { Ok::<_, Error>((time, self.historical_rate(currency, time).await?)) })
+            .collect();
+        let rates: Vec<_> = futures_util::stream::iter(lookups)
+            .buffer_unordered(MAX_CONCURRENT_PRICE_FETCHES)
+            .collect()
+            .await;
+        let rates: HashMap<u64, Option<f64>> = rates.into_iter().collect::<Result<_, _>>()?;
+
+        let mut values = Vec::new();
+        for (tx, block_time) in confirmed {
+            let Some(rate) = rates.get(&bucket(block_time)).copied().flatten() else {
+                continue;
+            };
+            let (funded, spent) = flows(tx, |spk| spk == &state.scriptpubkey);
+            let value = signed(funded) - signed(spent);
+            values.push(FiatValue {
+                txid: tx.txid,
+                block_time,
+                value,
+                currency: currency.to_string(),
+                rate,
+                fiat_value: value.to_btc() * rate,
+            });
+        }
+        Ok(values)
+    }
+
+    /// Price of one bitcoin in `currency` closest to the start of the hour `time` falls in
+    async fn historical_rate(&self, currency: &str, time: u64) -> Result<Option<f64>, Error> {
+        if let Some(rate) = self.prices.cached(currency, time) {
+            return Ok(Some(rate));
+        }
+        let historical = self.api.get_historical_price(Some(currency), Some(bucket(time))).await?;
+        let rate = historical.prices.first().and_then(|prices| prices.rate(currency));
+        if let Some(rate) = rate {
+            self.prices.cache(currency, time, rate);
+        }
+        Ok(rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_past_rates_by_hour() {
+        let feed = PriceFeed::new();
+        feed.cache("USD", 1_700_000_123, 37_000.0);
+        assert_eq!(feed.cached("USD", 1_699_999_200), Some(37_000.0));
+        assert_eq!(feed.cached("USD", 1_700_002_799), Some(37_000.0));
+        assert_eq!(feed.cached("USD", 1_700_002_800), None);
+        assert_eq!(feed.cached("EUR", 1_700_000_123), None);
+    }
+
+    #[test]
+    fn drops_the_oldest_past_rates_beyond_the_limit() {
+        let feed = PriceFeed::new();
+        let hours = u64::try_from(MAX_CACHED_PRICES).unwrap() + 2;
+        for hour in 0..hours {
+            feed.cache("USD", hour * PRICE_BUCKET, 1.0);
+        }
+        // caching a rate again does not count twice towards the limit
+        feed.cache("USD", (hours - 1) * PRICE_BUCKET, 2.0);
+
+        assert_eq!(feed.cached("USD", 0), None);
+        assert_eq!(feed.cached("USD", PRICE_BUCKET), None);
+        assert_eq!(feed.cached("USD", 2 * PRICE_BUCKET), Some(1.0));
+        assert_eq!(feed.cached("USD", (hours - 1) * PRICE_BUCKET), Some(2.0));
+        assert_eq!(feed.historical.lock().unwrap().order.len(), MAX_CACHED_PRICES);
+    }
+}
@@ -0,0 +1,449 @@
+//! Serialized representations of wallet types
+//!
+//! Every public wallet type implements `Serialize` and `Deserialize`.
+//! The representations below are stable within a [`SCHEMA_VERSION`], and are
+//! meant to be stored, or passed across the wasm boundary, wrapped in a
+//! [`Versioned`] envelope so that future versions can be told apart.
+//!
+//! # Conventions
+//!
+//! - scriptpubkeys, txids and block hashes are lowercase hex strings
+//! - amounts are integers in sats
+//! - enums are `{"type": "<snake_case variant>", "data": <contents>}`,
+//!   where `data` is omitted for variants without contents
+//! - transactions use the esplora REST API format
+//!
+//! # Examples
+//!
+//! A [`Versioned`] [`State`](super::address::State):
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "data": {
+//!     "scriptpubkey": "0014...",
+//!     "transactions": [<esplora tx>, ...],
+//!     "balance": {
+//!       "confirmed": 100000,
+//!       "trusted_pending": 0,
+//!       "untrusted_pending": 25000,
+//!       "outgoing_pending": 0,
+//!       "immature": 0
+//!     },
+//!     "sync_status": {"type": "ready"},
+//!     "packages": {
+//!       "<txid>": {"ancestors": [], "descendants": [], "effectiveFeePerVsize": 12.5}
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! A [`SequencedEvent`](super::SequencedEvent) holding a
+//! [`wallet::Event::AddressEvent`](super::Event::AddressEvent), whose data is an
+//! [`address::Event`](super::address::Event) of `[scriptpubkey, tx]`:
+//!
+//! ```json
+//! {
+//!   "seq": 1700000000000042,
+//!   "event": {
+//!     "type": "address_event",
+//!     "data": {"type": "confirmed", "data": ["0014...", <esplora tx>]}
+//!   }
+//! }
+//! ```
+//!
+//! An esplora transaction:
+//!
+//! ```json
+//! {
+//!   "txid": "...",
+//!   "version": 2,
+//!   "locktime": 0,
+//!   "vin": [{
+//!     "txid": "...",
+//!     "vout": 0,
+//!     "prevout": {"value": 100000, "scriptpubkey": "0014..."},
+//!     "scriptsig": "",
+//!     "witness": ["3044...", "02..."],
+//!     "sequence": 4294967293,
+//!     "is_coinbase": false
+//!   }],
+//!   "vout": [{"value": 99000, "scriptpubkey": "0014..."}],
+//!   "status": {"confirmed": true, "block_height": 800000, "block_hash": "...", "block_time": 1690000000},
+//!   "fee": 1000
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use bitcoin::{BlockHash, ScriptBuf, Txid};
+use esplora_client::{PrevOut, Tx, TxStatus, Vin, Vout};
+use hex::DisplayHex;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Version of the serialized representations described in this module
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A serialized wallet value, tagged with the schema version it was written with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Versioned<T> {
+    #[serde(deserialize_with = "deserialize_version")]
+    pub version: u32,
+    pub data: T,
+}
+
+impl<T> Versioned<T> {
+    /// Tag `data` with the current schema version
+    pub const fn new(data: T) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            data,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+fn deserialize_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let version = u32::deserialize(deserializer)?;
+    if version == 0 || version > SCHEMA_VERSION {
+        return Err(D::Error::custom(format!("unsupported schema version {version}")));
+    }
+    Ok(version)
+}
+
+#[derive(Serialize)]
+struct TxRef<'a> {
+    txid: &'a Txid,
+    version: i32,
+    locktime: u32,
+    vin: Vec<VinRef<'a>>,
+    vout: Vec<VoutRef<'a>>,
+    status: StatusRef<'a>,
+    fee: u64,
+}
+
+#[derive(Serialize)]
+struct VinRef<'a> {
+    txid: &'a Txid,
+    vout: u32,
+    prevout: Option<VoutRef<'a>>,
+    scriptsig: &'a ScriptBuf,
+    witness: Vec<String>,
+    sequence: u32,
+    is_coinbase: bool,
+}
+
+#[derive(Serialize)]
+struct VoutRef<'a> {
+    value: u64,
+    scriptpubkey: &'a ScriptBuf,
+}
+
+#[derive(Serialize)]
+struct StatusRef<'a> {
+    confirmed: bool,
+    block_height: Option<u32>,
+    block_hash: Option<&'a BlockHash>,
+    block_time: Option<u64>,
+}
+
+impl<'a> From<&'a Tx> for TxRef<'a> {
+    fn from(tx: &'a Tx) -> Self {
+        Self {
+            txid: &tx.txid,
+            version: tx.version,
+            locktime: tx.locktime,
+            vin: tx.vin.iter().map(VinRef::from).collect(),
+            vout: tx.vout.iter().map(VoutRef::from).collect(),
+            status: StatusRef::from(&tx.status),
+            fee: tx.fee,
+        }
+    }
+}
+
+impl<'a> From<&'a Vin> for VinRef<'a> {
+    fn from(vin: &'a Vin) -> Self {
+        Self {
+            txid: &vin.txid,
+            vout: vin.vout,
+            prevout: vin.prevout.as_ref().map(VoutRef::from),
+            scriptsig: &vin.scriptsig,
+            witness: vin.witness.iter().map(|item| item.as_slice().to_lower_hex_string()).collect(),
+            sequence: vin.sequence,
+            is_coinbase: vin.is_coinbase,
+        }
+    }
+}
+
+impl<'a> From<&'a Vout> for VoutRef<'a> {
+    fn from(vout: &'a Vout) -> Self {
+        Self {
+            value: vout.value,
+            scriptpubkey: &vout.scriptpubkey,
+        }
+    }
+}
+
+impl<'a> From<&'a PrevOut> for VoutRef<'a> {
+    fn from(prevout: &'a PrevOut) -> Self {
+        Self {
+            value: prevout.value,
+            scriptpubkey: &prevout.scriptpubkey,
+        }
+    }
+}
+
+impl<'a> From<&'a TxStatus> for StatusRef<'a> {
+    fn from(status: &'a TxStatus) -> Self {
+        Self {
+            confirmed: status.confirmed,
+            block_height: status.block_height,
+            block_hash: status.block_hash.as_ref(),
+            block_time: status.block_time,
+        }
+    }
+}
+
+/// (De)serialize an esplora [`Tx`] in the esplora REST API format
+pub(crate) mod tx {
+    use super::{Deserialize, Deserializer, Serialize, Serializer, Tx, TxRef};
+
+    pub fn serialize<S: Serializer>(tx: &Tx, serializer: S) -> Result<S::Ok, S::Error> {
+        TxRef::from(tx).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Tx, D::Error> {
+        Tx::deserialize(deserializer)
+    }
+}
+
+/// (De)serialize an esplora [`TxStatus`] in the esplora REST API format
+pub(crate) mod status {
+    use super::{Deserialize, Deserializer, Serialize, Serializer, StatusRef, TxStatus};
+
+    pub fn serialize<S: Serializer>(status: &TxStatus, serializer: S) -> Result<S::Ok, S::Error> {
+        StatusRef::from(status).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TxStatus, D::Error> {
+        TxStatus::deserialize(deserializer)
+    }
+}
+
+/// (De)serialize a shared [`Tx`] in the esplora REST API format
+pub(crate) mod arc_tx {
+    use super::{Arc, Deserialize, Deserializer, Serialize, Serializer, Tx, TxRef};
+
+    pub fn serialize<S: Serializer>(tx: &Arc<Tx>, serializer: S) -> Result<S::Ok, S::Error> {
+        TxRef::from(&**tx).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<Tx>, D::Error> {
+        Tx::deserialize(deserializer).map(Arc::new)
+    }
+}
+
+/// (De)serialize a list of shared [`Tx`]s in the esplora REST API format
+pub(crate) mod arc_txs {
+    use super::{Arc, Deserialize, Deserializer, Serializer, Tx, TxRef};
+    use serde::ser::SerializeSeq;
+
+    #[allow(clippy::ptr_arg)]
+    pub fn serialize<S: Serializer>(txs: &Vec<Arc<Tx>>, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(txs.len()))?;
+        for tx in txs {
+            seq.serialize_element(&TxRef::from(&**tx))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Arc<Tx>>, D::Error> {
+        Vec::<Tx>::deserialize(deserializer).map(|txs| txs.into_iter().map(Arc::new).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::OutPoint;
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::api::mempool::{CpfpInfo, CpfpTx};
+    use crate::test_utils::{spk, status, txid};
+    use crate::wallet::address::{self, Balance, State, SyncStatus};
+    use crate::wallet::summary::WalletTx;
+    use crate::wallet::{Event, SequencedEvent};
+
+    fn confirmed() -> TxStatus {
+        TxStatus {
+            confirmed: true,
+            block_height: Some(800_000),
+            block_hash: Some(BlockHash::from_byte_array([7; 32])),
+            block_time: Some(1_690_000_000),
+        }
+    }
+
+    fn tx(status: TxStatus) -> Tx {
+        Tx {
+            txid: txid(1),
+            version: 2,
+            locktime: 0,
+            vin: vec![
+                Vin {
+                    txid: txid(2),
+                    vout: 1,
+                    prevout: Some(PrevOut { value: 100_000, scriptpubkey: spk() }),
+                    scriptsig: ScriptBuf::new(),
+                    witness: vec![vec![0x30, 0x44], vec![0x02]],
+                    sequence: 0xffff_fffd,
+                    is_coinbase: false,
+                },
+                Vin {
+                    txid: Txid::all_zeros(),
+                    vout: u32::MAX,
+                    prevout: None,
+                    scriptsig: ScriptBuf::from(vec![0x03, 0x01]),
+                    witness: Vec::new(),
+                    sequence: u32::MAX,
+                    is_coinbase: true,
+                },
+            ],
+            vout: vec![Vout { value: 99_000, scriptpubkey: spk() }],
+            status,
+            fee: 1_000,
+        }
+    }
+
+    /// Serialize `value`, checking it deserializes to something which serializes the same
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> (T, Value) {
+        let json = serde_json::to_value(value).unwrap();
+        let parsed: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+        (parsed, json)
+    }
+
+    #[test]
+    fn tx_uses_esplora_format() {
+        let (parsed, json) = round_trip(&address::Event::Confirmed(spk(), tx(confirmed())));
+        assert_eq!(json["type"], "confirmed");
+        let tx_json = &json["data"][1];
+        assert_eq!(tx_json["vin"][0]["witness"], json!(["3044", "02"]));
+        assert_eq!(tx_json["vin"][0]["prevout"], json!({"value": 100_000, "scriptpubkey": "51"}));
+        assert_eq!(tx_json["vin"][1]["prevout"], Value::Null);
+        assert_eq!(tx_json["status"]["block_height"], 800_000);
+        assert_eq!(tx_json["status"]["block_hash"], "07".repeat(32));
+        assert_eq!(parsed.tx().vin[0].witness, [vec![0x30, 0x44], vec![0x02]]);
+        assert_eq!(parsed.tx().status, confirmed());
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let package_txid = txid(1);
+        let package = CpfpInfo {
+            ancestors: vec![CpfpTx { txid: txid(2), fee: 500, weight: 560 }],
+            effective_fee_per_vsize: Some(12.5),
+            ..CpfpInfo::default()
+        };
+        let state = State {
+            scriptpubkey: spk(),
+            transactions: vec![Arc::new(tx(confirmed())), Arc::new(tx(status(None)))],
+            balance: Balance {
+                confirmed: bitcoin::Amount::from_sat(100_000),
+                untrusted_pending: bitcoin::Amount::from_sat(25_000),
+                ..Balance::default()
+            },
+            sync_status: SyncStatus::Failed("timeout".to_string()),
+            packages: HashMap::from([(package_txid, package.clone())]),
+        };
+        let (parsed, json) = round_trip(&Versioned::new(state));
+        assert_eq!(json["version"], SCHEMA_VERSION);
+        assert_eq!(json["data"]["balance"]["untrusted_pending"], 25_000);
+        assert_eq!(json["data"]["sync_status"], json!({"type": "failed", "data": "timeout"}));
+        assert_eq!(json["data"]["transactions"][1]["status"], json!({
+            "confirmed": false, "block_height": null, "block_hash": null, "block_time": null
+        }));
+
+        let parsed = parsed.into_inner();
+        assert_eq!(parsed.transactions[0].status, confirmed());
+        assert_eq!(parsed.transactions[1].status, status(None));
+        assert_eq!(parsed.packages[&package_txid], package);
+        assert_eq!(parsed.balance, Balance {
+            confirmed: bitcoin::Amount::from_sat(100_000),
+            untrusted_pending: bitcoin::Amount::from_sat(25_000),
+            ..Balance::default()
+        });
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let events = [
+            Event::Initializing,
+            Event::AddressReady(spk()),
+            Event::SyncProgress {
+                scriptpubkey: spk(),
+                pages_fetched: 2,
+                txs_fetched: 50,
+                oldest_height_reached: None,
+            },
+            Event::AddressResync(address::Event::Removed(spk(), tx(status(None)))),
+            Event::OutpointSpent {
+                outpoint: OutPoint { txid: txid(2), vout: 1 },
+                spending_txid: txid(1),
+                status: confirmed(),
+            },
+            Event::OutpointSpent {
+                outpoint: OutPoint { txid: txid(2), vout: 1 },
+                spending_txid: txid(1),
+                status: status(None),
+            },
+        ];
+        for event in events {
+            let (parsed, json) = round_trip(&event);
+            assert_eq!(parsed.kind(), event.kind());
+            if let (Event::OutpointSpent { status: parsed, .. }, Event::OutpointSpent { status, .. }) = (&parsed, &event) {
+                assert_eq!(parsed, status);
+                assert_eq!(json["data"]["status"]["block_hash"].is_null(), status.block_hash.is_none());
+            }
+        }
+        assert_eq!(serde_json::to_value(Event::Initializing).unwrap(), json!({"type": "initializing"}));
+    }
+
+    #[test]
+    fn sequenced_event_round_trips() {
+        let sequenced = SequencedEvent {
+            seq: 1_700_000_000_000_042,
+            event: Event::AddressEvent(address::Event::Mempool(spk(), tx(status(None)))),
+        };
+        let (parsed, json) = round_trip(&Versioned::new(sequenced));
+        assert_eq!(json["data"]["seq"], 1_700_000_000_000_042_u64);
+        assert_eq!(json["data"]["event"]["type"], "address_event");
+        assert_eq!(json["data"]["event"]["data"]["type"], "mempool");
+        assert_eq!(parsed.data.seq, 1_700_000_000_000_042);
+
+        let unsupported = json!({"version": SCHEMA_VERSION + 1, "data": json["data"]});
+        assert!(serde_json::from_value::<Versioned<SequencedEvent>>(unsupported).is_err());
+    }
+
+    #[test]
+    fn shared_tx_round_trips() {
+        let wallet_tx = WalletTx {
+            tx: Arc::new(tx(confirmed())),
+            net_value: bitcoin::SignedAmount::from_sat(-1_000),
+            fee: bitcoin::Amount::from_sat(1_000),
+            feerate: 7.5,
+            addresses: vec![spk()],
+        };
+        let (parsed, json) = round_trip(&wallet_tx);
+        assert_eq!(json["net_value"], -1_000);
+        assert_eq!(json["tx"]["txid"], "01".repeat(32));
+        assert_eq!(parsed.tx.status, confirmed());
+    }
+}
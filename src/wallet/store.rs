@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+use bitcoin::Txid;
+use esplora_client::Tx;
+
+/// Wallet-wide store of transactions, shared between address trackers
+///
+/// Trackers hold reference-counted handles to the transactions they index,
+/// so a transaction involving many addresses is only held in memory once,
+/// and is dropped as soon as no tracker refers to it any more.
+#[derive(Debug, Clone, Default)]
+pub struct TxStore {
+    txs: Arc<Mutex<HashMap<Txid, Weak<Tx>>>>,
+}
+
+impl TxStore {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared copy of `tx`, adding it to the store if it is new
+    /// or replacing the stored version if its status has changed
+    pub fn intern(&self, tx: &Tx) -> Arc<Tx> {
+        let mut txs = self.lock();
+        if let Some(shared) = txs.get(&tx.txid).and_then(Weak::upgrade) {
+            if shared.status == tx.status {
+                return shared;
+            }
+        }
+        let shared = Arc::new(tx.clone());
+        txs.insert(tx.txid, Arc::downgrade(&shared));
+        shared
+    }
+
+    #[must_use]
+    pub fn get(&self, txid: &Txid) -> Option<Arc<Tx>> {
+        self.lock().get(txid).and_then(Weak::upgrade)
+    }
+
+    /// Forget `txid` if no tracker refers to it any more
+    pub fn release(&self, txid: &Txid) {
+        let mut txs = self.lock();
+        if txs.get(txid).is_some_and(|tx| tx.strong_count() == 0) {
+            txs.remove(txid);
+        }
+    }
+
+    /// Forget every transaction which no tracker refers to any more
+    pub fn prune(&self) {
+        self.lock().retain(|_, tx| tx.strong_count() > 0);
+    }
+
+    /// Number of unique transactions held by the store
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().values().filter(|tx| tx.strong_count() > 0).count()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Txid, Weak<Tx>>> {
+        self.txs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{funding, spk, status, theirs, tx};
+    use crate::wallet::address::{Event, SyncStatus, Tracker};
+    use crate::wallet::journal::EventJournal;
+    use crate::wallet::tip::ChainTip;
+
+    #[test]
+    fn interned_transactions_are_shared_until_their_status_changes() {
+        let store = TxStore::new();
+        let unconfirmed = store.intern(&funding(1, 1_000, None));
+        assert!(Arc::ptr_eq(&unconfirmed, &store.intern(&funding(1, 1_000, None))));
+        assert_eq!(store.len(), 1);
+
+        let confirmed = store.intern(&funding(1, 1_000, Some(100)));
+        assert_eq!(confirmed.status, status(Some(100)));
+        assert!(Arc::ptr_eq(&confirmed, &store.get(&confirmed.txid).unwrap()));
+        // the stale handle is still usable, but no longer shared
+        assert_eq!(unconfirmed.status, status(None));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn unreferenced_transactions_are_forgotten() {
+        let store = TxStore::new();
+        let first = store.intern(&funding(1, 1_000, None));
+        let second = store.intern(&funding(2, 1_000, None));
+        let txid = first.txid;
+
+        // still referred to
+        store.release(&txid);
+        assert!(store.get(&txid).is_some());
+
+        drop(first);
+        assert!(store.get(&txid).is_none());
+        assert_eq!(store.len(), 1);
+        store.release(&txid);
+        assert_eq!(store.lock().len(), 1);
+
+        drop(second);
+        assert!(store.is_empty());
+        store.prune();
+        assert!(store.lock().is_empty());
+    }
+
+    #[test]
+    fn trackers_share_transactions() {
+        let store = TxStore::new();
+        let tracker = |scriptpubkey| {
+            let mut tracker = Tracker::new(scriptpubkey, store.clone(), ChainTip::new(), EventJournal::new(0));
+            tracker.set_sync_status(SyncStatus::Ready);
+            tracker
+        };
+        let mut ours = tracker(spk());
+        let mut others = tracker(theirs());
+        let payment = tx(1, None, &[], &[(spk(), 1_000), (theirs(), 2_000)]);
+        ours.process_event(Event::Mempool(spk(), payment.clone()), true);
+        others.process_event(Event::Mempool(theirs(), payment), true);
+
+        assert_eq!(store.len(), 1);
+        let (ours_state, others_state) = (ours.get_state(), others.get_state());
+        assert!(Arc::ptr_eq(&ours_state.transactions[0], &others_state.transactions[0]));
+
+        drop((ours, ours_state));
+        assert_eq!(store.len(), 1);
+        drop((others, others_state));
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use bitcoin::amount::serde::as_sat;
+use bitcoin::{Amount, ScriptBuf, SignedAmount, Txid};
+use esplora_client::Tx;
+use serde::{Deserialize, Serialize};
+
+use super::address::{cmp_tx_time, flows, signed, Balance, State};
+use super::schema;
+
+/// A transaction's effect on the wallet as a whole
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletTx {
+    #[serde(with = "schema::arc_tx")]
+    pub tx: Arc<Tx>,
+    /// value received by the wallet minus value sent from the wallet
+    #[serde(with = "as_sat")]
+    pub net_value: SignedAmount,
+    #[serde(with = "as_sat")]
+    pub fee: Amount,
+    /// sats per virtual byte
+    pub feerate: f64,
+    /// wallet scriptpubkeys funded or spent from by this transaction
+    pub addresses: Vec<ScriptBuf>,
+}
+
+/// Wallet-wide view of every tracked address
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Summary {
+    /// every transaction involving the wallet, without duplicates, oldest first
+    pub transactions: Vec<WalletTx>,
+    /// balance of the wallet as a whole, where spending between
+    /// wallet addresses counts as trusted
+    pub balance: Balance,
+}
+
+impl Summary {
+    #[must_use]
+    pub fn from_states(states: &[State]) -> Self {
+        let scriptpubkeys: HashSet<&ScriptBuf> = states.iter().map(|state| &state.scriptpubkey).collect();
+
+        let mut unique: HashMap<Txid, &Arc<Tx>> = HashMap::new();
+        for tx in states.iter().flat_map(|state| &state.transactions) {
+            unique.insert(tx.txid, tx);
+        }
+        let mut txs: Vec<&Arc<Tx>> = unique.into_values().collect();
+        txs.sort_by(|a, b| cmp_tx_time(a, b));
+
+        let mut summary = Self::default();
+        for state in states {
+            summary.balance.confirmed += state.balance.confirmed;
+            summary.balance.immature += state.balance.immature;
+        }
+        summary.balance.add_pending(txs.iter().map(|tx| &***tx), |spk| scriptpubkeys.contains(spk));
+        for tx in txs {
+            summary.transactions.push(WalletTx::new(tx, &scriptpubkeys));
+        }
+
+        summary
+    }
+}
+
+impl WalletTx {
+    fn new(tx: &Arc<Tx>, scriptpubkeys: &HashSet<&ScriptBuf>) -> Self {
+        let mut addresses: Vec<ScriptBuf> = tx
+            .vin
+            .iter()
+            .filter_map(|vin| vin.prevout.as_ref().map(|prevout| &prevout.scriptpubkey))
+            .chain(tx.vout.iter().map(|vout| &vout.scriptpubkey))
+            .filter(|spk| scriptpubkeys.contains(spk))
+            .cloned()
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+
+        let (received, sent) = flows(tx, |spk| scriptpubkeys.contains(spk));
+
+        #[allow(clippy::cast_precision_loss)]
+        let feerate = tx.fee as f64 / tx.to_tx().vsize() as f64;
+
+        Self {
+            tx: Arc::clone(tx),
+            net_value: signed(received) - signed(sent),
+            fee: Amount::from_sat(tx.fee),
+            feerate,
+            addresses,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{spk, theirs, tx};
+    use crate::wallet::address::SyncStatus;
+
+    /// Another address of the wallet
+    fn change() -> ScriptBuf {
+        ScriptBuf::from(vec![0x53])
+    }
+
+    fn state(scriptpubkey: ScriptBuf, transactions: &[&Arc<Tx>], confirmed: u64) -> State {
+        State {
+            scriptpubkey,
+            transactions: transactions.iter().map(|tx| Arc::clone(tx)).collect(),
+            balance: Balance {
+                confirmed: Amount::from_sat(confirmed),
+                ..Balance::default()
+            },
+            sync_status: SyncStatus::Ready,
+            packages: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn transactions_between_addresses_are_counted_once() {
+        let receive = Arc::new(tx(1, Some(100), &[(9, theirs(), 11_000)], &[(spk(), 10_000)]));
+        let mut spend = tx(2, None, &[(1, spk(), 10_000)], &[(theirs(), 6_000), (change(), 3_000)]);
+        spend.fee = 1_000;
+        let spend = Arc::new(spend);
+
+        let summary = Summary::from_states(&[
+            state(spk(), &[&receive, &spend], 10_000),
+            state(change(), &[&spend], 0),
+        ]);
+
+        let txids: Vec<Txid> = summary.transactions.iter().map(|wallet_tx| wallet_tx.tx.txid).collect();
+        assert_eq!(txids, [receive.txid, spend.txid]);
+        assert_eq!(summary.transactions[0].net_value, SignedAmount::from_sat(10_000));
+        assert_eq!(summary.transactions[0].addresses, [spk()]);
+        assert_eq!(summary.transactions[1].net_value, SignedAmount::from_sat(-7_000));
+        assert_eq!(summary.transactions[1].fee, Amount::from_sat(1_000));
+        assert_eq!(summary.transactions[1].addresses, [spk(), change()]);
+
+        // change sent to another wallet address is trusted
+        assert_eq!(summary.balance.confirmed, Amount::from_sat(10_000));
+        assert_eq!(summary.balance.outgoing_pending, Amount::from_sat(10_000));
+        assert_eq!(summary.balance.trusted_pending, Amount::from_sat(3_000));
+        assert_eq!(summary.balance.untrusted_pending, Amount::ZERO);
+    }
+
+    #[test]
+    fn empty_wallet() {
+        let summary = Summary::from_states(&[state(spk(), &[], 0)]);
+        assert!(summary.transactions.is_empty());
+        assert_eq!(summary.balance, Balance::default());
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Height of the best block the wallet has seen, shared between trackers
+#[derive(Debug, Clone, Default)]
+pub struct ChainTip {
+    /// zero until the first block is seen
+    height: Arc<AtomicU32>,
+}
+
+impl ChainTip {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn height(&self) -> Option<u32> {
+        Some(self.height.load(Ordering::Relaxed)).filter(|height| *height > 0)
+    }
+
+    /// Record a block at `height`, which only moves the tip forwards
+    pub fn update(&self, height: u32) {
+        self.height.fetch_max(height, Ordering::Relaxed);
+    }
+}